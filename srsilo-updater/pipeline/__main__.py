@@ -2,13 +2,15 @@
 
 Usage:
     python -m pipeline --config /opt/srsilo/pipeline.yml [--virus covid]
+        [--force] [--since <timestamp|YYYY-MM-DD> | --reset-state]
 """
 
 import argparse
 import logging
-import shutil
+import os
 import sys
 from pathlib import Path
+from typing import Optional
 
 from pipeline.config import PipelineConfig
 from pipeline.phases import check_new_data, cleanup, fetch, preprocessing, sort_and_merge
@@ -23,15 +25,20 @@ def setup_logging() -> None:
     )
 
 
-def run_virus(virus_name: str, config: PipelineConfig) -> bool:
-    """Run the full pipeline for one virus. Returns True on success."""
+def run_virus(virus_name: str, config: PipelineConfig, has_new_data: Optional[bool] = None) -> bool:
+    """Run the full pipeline for one virus. Returns True on success.
+
+    `has_new_data` is the result of a combined check (see check_new_data.run_all);
+    if omitted, the virus is checked on its own.
+    """
     log = logging.getLogger(f"pipeline.{virus_name}")
     virus = config.viruses[virus_name]
     paths = config.virus_paths(virus_name)
 
     log.info("======== %s ========", virus_name.upper())
 
-    has_new_data = check_new_data.run(config, virus, paths)
+    if has_new_data is None:
+        has_new_data = check_new_data.run(config, virus, paths)
     if not has_new_data:
         log.info("Nothing to do for %s", virus_name)
         return True
@@ -42,10 +49,9 @@ def run_virus(virus_name: str, config: PipelineConfig) -> bool:
         sort_and_merge.run(config, virus, paths)
         preprocessing.run(config, virus, paths)
 
-        # promote timestamp
+        # promote state (atomic rename)
         if paths.next_timestamp.exists():
-            shutil.copy2(paths.next_timestamp, paths.last_update)
-            paths.next_timestamp.unlink()
+            paths.next_timestamp.replace(paths.last_update)
 
     except Exception as exc:
         log.error("Pipeline failed for %s: %s", virus_name, exc)
@@ -62,20 +68,43 @@ def main() -> None:
     parser = argparse.ArgumentParser(description="srSILO update pipeline")
     parser.add_argument("--config", type=Path, required=True, help="Path to pipeline.yml")
     parser.add_argument("--virus", help="Process a single virus (default: all enabled viruses)")
+    parser.add_argument("--force", action="store_true", help="Run even if no new data is found")
+    refresh = parser.add_mutually_exclusive_group()
+    refresh.add_argument("--since", help="Backfill submissions since a Unix timestamp or YYYY-MM-DD")
+    refresh.add_argument(
+        "--reset-state", action="store_true", help="Ignore .last_update and check as on a first run"
+    )
     args = parser.parse_args()
 
+    check_args = []
+    if args.force:
+        check_args.append("--force")
+    if args.since:
+        check_args += ["--since", args.since]
+    if args.reset_state:
+        check_args.append("--reset-state")
+
     config = PipelineConfig.load(args.config)
+    if config.webhook_url:
+        # Inherited by every binary the phases run
+        os.environ.setdefault("SRSILO_WEBHOOK_URL", config.webhook_url)
 
     viruses = [args.virus] if args.virus else config.enabled_viruses
     log.info("Processing %d virus(es): %s", len(viruses), ", ".join(viruses))
 
-    failures = []
-    for virus_name in viruses:
-        if virus_name not in config.viruses:
-            log.error("Unknown virus: %s", virus_name)
+    failures = [v for v in viruses if v not in config.viruses]
+    for virus_name in failures:
+        log.error("Unknown virus: %s", virus_name)
+
+    known = [v for v in viruses if v in config.viruses]
+    checks = check_new_data.run_all(config, known, check_args) if known else {}
+
+    for virus_name in known:
+        has_new_data = checks.get(virus_name)
+        if has_new_data is None:
             failures.append(virus_name)
             continue
-        ok = run_virus(virus_name, config)
+        ok = run_virus(virus_name, config, has_new_data)
         if not ok:
             failures.append(virus_name)
 
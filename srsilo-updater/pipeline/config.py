@@ -2,7 +2,7 @@ from __future__ import annotations
 
 from dataclasses import dataclass, field
 from pathlib import Path
-from typing import Dict, List
+from typing import Dict, List, Optional
 
 import yaml
 
@@ -17,6 +17,8 @@ class VirusConfig:
     fetch_max_reads: int
     chunk_size: int
     docker_memory_limit: str
+    chunk_memory_limit: Optional[str] = None  # e.g. "8G"; flushes chunks by size
+    dedup_policy: Optional[str] = None  # error | keep-first | keep-last-by-source; off if unset
 
 
 @dataclass
@@ -28,6 +30,8 @@ class PipelineConfig:
     retention_min_keep: int
     enabled_viruses: List[str]
     viruses: Dict[str, VirusConfig]
+    source: Optional[Path] = None  # pipeline.yml this config was loaded from
+    webhook_url: Optional[str] = None  # passed to the binaries as SRSILO_WEBHOOK_URL
 
     @classmethod
     def load(cls, path: Path) -> "PipelineConfig":
@@ -44,6 +48,8 @@ class PipelineConfig:
                 fetch_max_reads=int(cfg["fetch_max_reads"]),
                 chunk_size=int(cfg["chunk_size"]),
                 docker_memory_limit=cfg["docker_memory_limit"],
+                chunk_memory_limit=cfg.get("chunk_memory_limit") or None,
+                dedup_policy=cfg.get("dedup_policy") or None,
             )
             for name, cfg in data["viruses"].items()
         }
@@ -56,6 +62,8 @@ class PipelineConfig:
             retention_min_keep=int(data["retention_min_keep"]),
             enabled_viruses=data["enabled_viruses"],
             viruses=viruses,
+            source=Path(path),
+            webhook_url=data.get("webhook_url") or None,
         )
 
     def virus_paths(self, virus: str) -> "VirusPaths":
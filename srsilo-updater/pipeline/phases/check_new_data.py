@@ -1,14 +1,31 @@
+import json
 import logging
 import subprocess
+import tempfile
 from pathlib import Path
+from typing import Dict, List, Optional
 
 from pipeline.config import PipelineConfig, VirusConfig, VirusPaths
 
 log = logging.getLogger(__name__)
 
+# Exit code of all Rust tools when another run holds the organism lock
+LOCK_HELD_EXIT_CODE = 3
 
-def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> bool:
-    """Return True if new data is available, False if nothing to do."""
+# Exit code of check_new_data when API timestamps disagree with the local clock
+CLOCK_SKEW_EXIT_CODE = 4
+
+
+def run(
+    config: PipelineConfig,
+    virus: VirusConfig,
+    paths: VirusPaths,
+    extra_args: Optional[List[str]] = None,
+) -> bool:
+    """Return True if new data is available, False if nothing to do.
+
+    `extra_args` are passed on to check_new_data (e.g. --force, --since, --reset-state).
+    """
     log.info("PHASE 2: Checking for new data")
     result = subprocess.run(
         [
@@ -18,6 +35,10 @@ def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> bool:
             "--timestamp-file", str(paths.last_update),
             "--days-back", str(virus.fetch_days),
             "--output-timestamp-file", str(paths.next_timestamp),
+            # A corrupted state is moved aside and treated as a first run
+            "--recover-state",
+            "--lock-dir", str(paths.base),
+            *(extra_args or []),
         ],
         cwd=paths.base,
     )
@@ -28,5 +49,64 @@ def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> bool:
     elif result.returncode == 1:
         log.info("PHASE 2: No new data — skipping pipeline")
         return False
+    elif result.returncode == LOCK_HELD_EXIT_CODE:
+        log.warning("PHASE 2: Another pipeline run holds the lock — skipping")
+        return False
+    elif result.returncode == CLOCK_SKEW_EXIT_CODE:
+        raise RuntimeError("check_new_data refused to advance the checkpoint (clock skew, see its output)")
     else:
         raise RuntimeError(f"check_new_data exited with code {result.returncode}")
+
+
+def run_all(
+    config: PipelineConfig,
+    virus_names: List[str],
+    extra_args: Optional[List[str]] = None,
+) -> Dict[str, Optional[bool]]:
+    """Check several viruses concurrently with a single check_new_data invocation.
+
+    Returns per virus True if new data is available, False if nothing to do
+    (including when another run holds its lock), and None if its check failed.
+    """
+    if config.source is None:
+        raise RuntimeError("run_all needs a config loaded from pipeline.yml")
+
+    log.info("PHASE 2: Checking for new data (%s)", ", ".join(virus_names))
+    with tempfile.TemporaryDirectory() as tmp:
+        json_output = Path(tmp) / "check_results.json"
+        result = subprocess.run(
+            [
+                config.binaries() / "check_new_data",
+                "--config", str(config.source),
+                "--organisms", ",".join(virus_names),
+                "--json-output", str(json_output),
+                # A corrupted state is moved aside and treated as a first run
+                "--recover-state",
+                *(extra_args or []),
+            ],
+            cwd=config.base_path,
+        )
+        if not json_output.exists():
+            raise RuntimeError(f"check_new_data exited with code {result.returncode}")
+        report = json.loads(json_output.read_text())
+
+    statuses = {}
+    for entry in report["results"]:
+        name, status = entry["name"], entry["status"]
+        if status == "new_data":
+            log.info("PHASE 2: %s: new data available — pipeline will run", name)
+            statuses[name] = True
+        elif status == "no_new_data":
+            log.info("PHASE 2: %s: no new data — skipping pipeline", name)
+            statuses[name] = False
+        elif status == "locked":
+            log.warning("PHASE 2: %s: another pipeline run holds the lock — skipping", name)
+            statuses[name] = False
+        elif status == "clock_skew":
+            log.error("PHASE 2: %s: check_new_data refused to advance the checkpoint (clock skew): %s",
+                      name, entry["error"])
+            statuses[name] = None
+        else:
+            log.error("PHASE 2: %s: check failed: %s", name, entry["error"])
+            statuses[name] = None
+    return statuses
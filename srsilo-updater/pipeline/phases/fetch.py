@@ -22,6 +22,8 @@ def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> None:
             "--max-reads", str(virus.fetch_max_reads),
             "--output-dir", str(paths.input),
             "--api-base-url", config.api_base_url,
+            "--state-file", str(paths.next_timestamp),
+            "--lock-dir", str(paths.base),
         ],
         cwd=paths.base,
         check=True,
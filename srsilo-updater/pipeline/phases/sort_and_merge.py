@@ -6,7 +6,12 @@ from pipeline.config import PipelineConfig, VirusConfig, VirusPaths
 
 log = logging.getLogger(__name__)
 
-SORT_FIELD = "/main/offset"
+# Offset first, read ID to break ties, so the output order is fully deterministic
+SORT_KEY = "/main/offset:int,/readId:string"
+# Passed to both tools so a record cannot sort in split and then fail the merge
+MISSING_KEY_POLICY = "error"
+# SILO's primary key; the last sort key, so copies of a read end up next to each other
+DEDUP_KEY = "/readId"
 
 
 def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> None:
@@ -15,71 +20,103 @@ def run(config: PipelineConfig, virus: VirusConfig, paths: VirusPaths) -> None:
     if not input_files:
         raise RuntimeError(f"No input files found in {paths.input}")
 
-    log.info("PHASE 6a: Splitting %d file(s) into sorted chunks (chunk_size=%d)",
-             len(input_files), virus.chunk_size)
+    # Gate before sorting: records SILO preprocessing would reject fail here
+    database_config = paths.config / "database_config.yaml"
+    if database_config.exists():
+        log.info("PHASE 6a: Validating %d file(s) against %s", len(input_files), database_config)
+        result = subprocess.run(
+            [
+                str(config.binaries() / "validate_records"),
+                "--database-config", str(database_config),
+                "--reference-genomes", str(paths.config / "reference_genomes.json"),
+                *[str(f) for f in input_files],
+            ],
+            cwd=paths.base,
+        )
+        if result.returncode != 0:
+            raise RuntimeError("Input records do not match the SILO schema")
+    else:
+        log.warning("PHASE 6a: %s not found, skipping record validation", database_config)
+
+    log.info("PHASE 6a: Splitting %d file(s) into sorted chunks (chunk_size=%d, memory_limit=%s)",
+             len(input_files), virus.chunk_size, virus.chunk_memory_limit or "none")
+    memory_args = (
+        ["--memory-limit", virus.chunk_memory_limit] if virus.chunk_memory_limit else []
+    )
 
     chunks_list = paths.sorted_chunks / "chunks.list"
     chunks_list.unlink(missing_ok=True)
 
     bins = config.binaries()
 
-    for input_file in input_files:
-        chunk_output = paths.sorted_chunks / input_file.name
-        chunk_output.mkdir(parents=True, exist_ok=True)
-
-        # zstdcat <file> | split_into_sorted_chunks --output-path <dir> ...
-        zstdcat = subprocess.Popen(
-            ["zstdcat", str(input_file)],
-            stdout=subprocess.PIPE,
-        )
-        subprocess.run(
-            [
-                str(bins / "split_into_sorted_chunks"),
-                "--output-path", str(chunk_output),
-                "--chunk-size", str(virus.chunk_size),
-                "--sort-field-path", SORT_FIELD,
-            ],
-            stdin=zstdcat.stdout,
-            cwd=paths.base,
-            check=True,
-        )
-        zstdcat.wait()
-        if zstdcat.returncode != 0:
-            raise RuntimeError(f"zstdcat failed for {input_file.name}")
-
-        # Append chunk paths to the list file
-        chunk_files = sorted(chunk_output.glob("chunk_*.ndjson.zst"))
-        with chunks_list.open("a") as f:
-            for c in chunk_files:
-                f.write(str(c) + "\n")
+    # Decodes all input files itself and writes the chunk paths in order to chunks.list
+    subprocess.run(
+        [
+            str(bins / "split_into_sorted_chunks"),
+            "--output-path", str(paths.sorted_chunks / "chunks"),
+            "--chunk-list", str(chunks_list),
+            "--chunk-size", str(virus.chunk_size),
+            *memory_args,
+            "--sort-key", SORT_KEY,
+            "--missing-key", MISSING_KEY_POLICY,
+            "--lock-dir", str(paths.base),
+            *[str(f) for f in input_files],
+        ],
+        stdout=subprocess.DEVNULL,
+        cwd=paths.base,
+        check=True,
+    )
 
     chunk_count = sum(1 for _ in chunks_list.open())
     log.info("PHASE 6a: Created %d chunk(s)", chunk_count)
 
-    log.info("PHASE 6a: Merging chunks -> %s", paths.sorted_file)
+    log.info("PHASE 6a: Merging chunks -> %s (dedup_policy=%s)",
+             paths.sorted_file, virus.dedup_policy or "none")
+    duplicate_report = paths.sorted_chunks / "duplicates.ndjson"
+    dedup_args = (
+        [
+            "--dedup-key", DEDUP_KEY,
+            "--dedup-policy", virus.dedup_policy,
+            "--duplicate-report", str(duplicate_report),
+        ]
+        if virus.dedup_policy
+        else []
+    )
 
-    # cat chunks.list | merge_sorted_chunks ... | zstd > sorted.ndjson.zst
+    # merge_sorted_chunks compresses itself and renames the file into place when done.
+    # It removes chunks and intermediate files as it merges them, so tmp/ and
+    # sorted_chunks/ do not hold several copies of the data at once.
     with chunks_list.open() as chunk_input:
-        merge = subprocess.Popen(
+        result = subprocess.run(
             [
                 str(bins / "merge_sorted_chunks"),
                 "--tmp-directory", str(paths.tmp),
-                "--sort-field-path", SORT_FIELD,
+                "--sort-key", SORT_KEY,
+                "--missing-key", MISSING_KEY_POLICY,
+                "--output", str(paths.sorted_file),
+                "--delete-chunks",
+                *dedup_args,
+                "--lock-dir", str(paths.base),
             ],
             stdin=chunk_input,
-            stdout=subprocess.PIPE,
             cwd=paths.base,
         )
-        with paths.sorted_file.open("wb") as out:
-            subprocess.run(
-                ["zstd"],
-                stdin=merge.stdout,
-                stdout=out,
-                check=True,
-            )
-        merge.wait()
-        if merge.returncode != 0:
-            raise RuntimeError("merge_sorted_chunks failed")
+    if result.returncode != 0:
+        raise RuntimeError("merge_sorted_chunks failed")
+
+    # Gate before SILO preprocessing: exit code 1 means out of order, 2 an error
+    log.info("PHASE 6a: Verifying sort order of %s", paths.sorted_file)
+    result = subprocess.run(
+        [
+            str(bins / "verify_sorted"),
+            "--sort-key", SORT_KEY,
+            "--missing-key", MISSING_KEY_POLICY,
+            str(paths.sorted_file),
+        ],
+        cwd=paths.base,
+    )
+    if result.returncode != 0:
+        raise RuntimeError(f"{paths.sorted_file} failed the sort order check")
 
     size_mb = paths.sorted_file.stat().st_size / 1024 / 1024
     log.info("PHASE 6a: Sort/merge complete — %.1f MB", size_mb)
@@ -0,0 +1,161 @@
+//! Sanity checks for submission timestamps against the local clock.
+//!
+//! A LAPIS server with a misconfigured clock can return `submittedAtTimestamp`
+//! values in the future. Written to the checkpoint, such a value would make
+//! every following run skip real submissions until that time has passed. The
+//! checkpoint is therefore never advanced beyond local time plus a tolerance
+//! (`--max-clock-skew`), and a checkpoint that already lies in the future is
+//! reported instead of silently used.
+
+use std::fmt;
+
+/// Default tolerance in seconds for API timestamps ahead of the local clock.
+pub const DEFAULT_MAX_CLOCK_SKEW: i64 = 300;
+
+/// Exit code when a timestamp fails these checks; the state is left untouched.
+pub const CLOCK_SKEW_EXIT_CODE: i32 = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum TimestampError {
+    /// The API returned a submission timestamp beyond local time plus tolerance
+    Future {
+        max_timestamp: i64,
+        now: i64,
+        tolerance: i64,
+    },
+    /// The promoted checkpoint itself lies beyond local time plus tolerance
+    CheckpointInFuture {
+        checkpoint: i64,
+        now: i64,
+        tolerance: i64,
+    },
+    /// The API returned a maximum timestamp not after the checkpoint despite
+    /// filtering on it, so the server and the state disagree
+    NotAfterCheckpoint { max_timestamp: i64, checkpoint: i64 },
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::Future {
+                max_timestamp,
+                now,
+                tolerance,
+            } => write!(
+                f,
+                "API returned submittedAtTimestamp {} which is {}s ahead of the local clock \
+                 ({}, tolerance {}s); refusing to advance the checkpoint past now",
+                max_timestamp,
+                max_timestamp - now,
+                now,
+                tolerance
+            ),
+            TimestampError::CheckpointInFuture {
+                checkpoint,
+                now,
+                tolerance,
+            } => write!(
+                f,
+                "Checkpoint {} is {}s ahead of the local clock ({}, tolerance {}s); \
+                 submissions before it would be skipped. Rerun with --since or --reset-state once the clocks agree.",
+                checkpoint,
+                checkpoint - now,
+                now,
+                tolerance
+            ),
+            TimestampError::NotAfterCheckpoint {
+                max_timestamp,
+                checkpoint,
+            } => write!(
+                f,
+                "API returned submittedAtTimestamp {} although only submissions after the \
+                 checkpoint {} were requested; refusing to move the checkpoint back",
+                max_timestamp, checkpoint
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+/// Checks that the promoted checkpoint is not in the future.
+pub fn check_checkpoint(checkpoint: i64, now: i64, tolerance: i64) -> Result<(), TimestampError> {
+    if checkpoint > now + tolerance {
+        return Err(TimestampError::CheckpointInFuture {
+            checkpoint,
+            now,
+            tolerance,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the maximum submission timestamp before it becomes the next checkpoint.
+pub fn check_max_timestamp(
+    max_timestamp: i64,
+    checkpoint: Option<i64>,
+    now: i64,
+    tolerance: i64,
+) -> Result<(), TimestampError> {
+    if max_timestamp > now + tolerance {
+        return Err(TimestampError::Future {
+            max_timestamp,
+            now,
+            tolerance,
+        });
+    }
+    if let Some(checkpoint) = checkpoint {
+        if max_timestamp <= checkpoint {
+            return Err(TimestampError::NotAfterCheckpoint {
+                max_timestamp,
+                checkpoint,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1751500000;
+
+    #[test]
+    fn test_max_timestamp_within_tolerance() {
+        assert_eq!(
+            check_max_timestamp(NOW - 60, Some(NOW - 3600), NOW, 300),
+            Ok(())
+        );
+        assert_eq!(check_max_timestamp(NOW + 300, None, NOW, 300), Ok(()));
+    }
+
+    #[test]
+    fn test_future_max_timestamp_is_refused() {
+        assert_eq!(
+            check_max_timestamp(NOW + 301, Some(NOW - 3600), NOW, 300),
+            Err(TimestampError::Future {
+                max_timestamp: NOW + 301,
+                now: NOW,
+                tolerance: 300
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_timestamp_not_after_checkpoint_is_refused() {
+        assert!(matches!(
+            check_max_timestamp(NOW - 3600, Some(NOW - 3600), NOW, 300),
+            Err(TimestampError::NotAfterCheckpoint { .. })
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_in_future() {
+        assert_eq!(check_checkpoint(NOW + 100, NOW, 300), Ok(()));
+        assert!(matches!(
+            check_checkpoint(NOW + 86400, NOW, 300),
+            Err(TimestampError::CheckpointInFuture { .. })
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+//! Diff mode: compare the upstream samples with the inventory of the live index.
+//!
+//! The inventory is the `samples` map of the promoted state, recorded by
+//! `fetch_silo_data` for the last successful run. Upstream, all samples in the
+//! sampling window (narrowed like the regular check, see
+//! `sampling_window_start`) and all revocations are listed, page by page.
+//! Samples are reported as added, updated (different version), revoked, or
+//! removed (in the index but no longer in the window). Diff mode only reads
+//! the state.
+
+use crate::{
+    build_details_url, load_previous_state, sampling_window_start, ApiResponse, Args, ChangeFilter,
+    Result, SampleData, Target,
+};
+use chrono::Utc;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+
+/// Samples requested per details request when listing samples.
+const SAMPLE_LIST_PAGE_SIZE: usize = 10_000;
+
+/// One added, updated or revoked sample.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct SampleChange {
+    sample_id: String,
+    /// Version in the live index (`None` for added samples or unknown versions)
+    previous_version: Option<i64>,
+    version: Option<i64>,
+    version_status: Option<String>,
+    version_comment: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct SampleDiff {
+    added: Vec<SampleChange>,
+    updated: Vec<SampleChange>,
+    revoked: Vec<SampleChange>,
+    /// Samples in the index that are no longer in the sampling window
+    removed: Vec<String>,
+}
+
+impl SampleDiff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.updated.is_empty()
+            && self.revoked.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+/// Prints the diff (and writes it as JSON if requested). Returns whether anything differs.
+pub async fn run_diff(args: &Args, target: &Target) -> Result<bool> {
+    let previous_state = load_previous_state(target, args.recover_state)?;
+    let (inventory, fetch_window) = match &previous_state {
+        Some(state) => (state.samples.clone(), state.fetch_window.as_ref()),
+        None => {
+            println!("No previous state found - every upstream sample counts as added.");
+            (BTreeMap::new(), None)
+        }
+    };
+
+    let window_start = sampling_window_start(Utc::now(), target.days_back, fetch_window);
+    let window_start = window_start.format("%Y-%m-%d").to_string();
+    println!(
+        "Comparing {} sample(s) in the index with upstream samples since {}",
+        inventory.len(),
+        window_start
+    );
+
+    let client = Client::new();
+    let upstream = list_samples(
+        &client,
+        target,
+        &ChangeFilter::submissions(0, &window_start),
+        "Sample list",
+    )
+    .await?;
+    let revocations = list_samples(
+        &client,
+        target,
+        &ChangeFilter::revocations(0),
+        "Revocation list",
+    )
+    .await?;
+
+    let diff = compute_diff(&inventory, &upstream, &revocations);
+    print_diff(&diff);
+
+    if let Some(diff_output) = &args.diff_output {
+        fs::write(diff_output, serde_json::to_string_pretty(&diff)? + "\n")?;
+        println!("Diff written to: {}", diff_output);
+    }
+
+    Ok(!diff.is_empty())
+}
+
+/// Builds the POST body for one page of the samples matching `filter` with
+/// their versions. The order is fixed so that pages do not overlap.
+fn build_sample_list_body(filter: &ChangeFilter, offset: usize) -> Value {
+    let mut body = json!({
+        "submittedAtTimestampFrom": filter.submitted_at_timestamp_from,
+        "fields": ["sampleId", "version", "submittedAtTimestamp", "versionStatus", "versionComment"],
+        "orderBy": [
+            {"field": "sampleId", "type": "ascending"},
+            {"field": "version", "type": "ascending"},
+        ],
+        "limit": SAMPLE_LIST_PAGE_SIZE,
+        "offset": offset,
+        "dataFormat": "JSON",
+    });
+    if let Some(date) = &filter.sampling_date_from {
+        body["samplingDateFrom"] = json!(date);
+    }
+    if filter.is_revocation {
+        body["isRevocation"] = json!(true);
+    }
+    body
+}
+
+/// Lists all samples matching `filter`, one page of `SAMPLE_LIST_PAGE_SIZE` at a time.
+async fn list_samples(
+    client: &Client,
+    target: &Target,
+    filter: &ChangeFilter,
+    label: &str,
+) -> Result<Vec<SampleData>> {
+    let url = build_details_url(&target.api_base_url, &target.organism);
+    let mut samples = Vec::new();
+    loop {
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json")
+            .json(&build_sample_list_body(filter, samples.len()))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!("{} API request failed: {}", label, response.status()).into());
+        }
+
+        let page: ApiResponse = response.json().await?;
+        let last_page = page.data.len() < SAMPLE_LIST_PAGE_SIZE;
+        samples.extend(page.data);
+        if last_page {
+            return Ok(samples);
+        }
+    }
+}
+
+fn change_for(sample: &SampleData, previous_version: Option<i64>) -> Option<SampleChange> {
+    Some(SampleChange {
+        sample_id: sample.sample_id.clone()?,
+        previous_version,
+        version: sample.version,
+        version_status: sample.version_status.clone(),
+        version_comment: sample.version_comment.clone(),
+    })
+}
+
+/// Compares the inventory with the upstream samples and revocations.
+///
+/// Upstream samples listed in several versions count with their highest
+/// version. Revocations only matter for samples in the index, and only if
+/// they are newer than both the indexed and the latest upstream version;
+/// an older one was superseded by a new submission.
+fn compute_diff(
+    inventory: &BTreeMap<String, Option<i64>>,
+    upstream: &[SampleData],
+    revocations: &[SampleData],
+) -> SampleDiff {
+    let mut latest: BTreeMap<&str, &SampleData> = BTreeMap::new();
+    for sample in upstream {
+        let Some(id) = sample.sample_id.as_deref() else {
+            continue;
+        };
+        match latest.get(id) {
+            Some(existing) if existing.version >= sample.version => {}
+            _ => {
+                latest.insert(id, sample);
+            }
+        }
+    }
+
+    let mut diff = SampleDiff::default();
+
+    let mut latest_revocations: BTreeMap<&str, &SampleData> = BTreeMap::new();
+    for sample in revocations {
+        let Some(id) = sample.sample_id.as_deref() else {
+            continue;
+        };
+        if !inventory.contains_key(id) {
+            continue;
+        }
+        match latest_revocations.get(id) {
+            Some(existing) if existing.version >= sample.version => {}
+            _ => {
+                latest_revocations.insert(id, sample);
+            }
+        }
+    }
+
+    let mut revoked_ids: HashSet<&str> = HashSet::new();
+    for (id, revocation) in latest_revocations {
+        let previous_version = inventory[id];
+        let current_version = latest
+            .get(id)
+            .and_then(|sample| sample.version)
+            .max(previous_version);
+        // Without versions to compare, the revocation counts
+        let is_newer = match (revocation.version, current_version) {
+            (Some(revoked), Some(current)) => revoked > current,
+            _ => true,
+        };
+        if is_newer {
+            revoked_ids.insert(id);
+            diff.revoked
+                .extend(change_for(revocation, previous_version));
+        }
+    }
+
+    for (id, sample) in &latest {
+        if revoked_ids.contains(id) {
+            continue;
+        }
+        match inventory.get(*id) {
+            None => diff.added.extend(change_for(sample, None)),
+            Some(previous_version) if *previous_version != sample.version => {
+                diff.updated.extend(change_for(sample, *previous_version))
+            }
+            Some(_) => {}
+        }
+    }
+
+    diff.removed = inventory
+        .keys()
+        .filter(|id| !latest.contains_key(id.as_str()) && !revoked_ids.contains(id.as_str()))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+fn format_version(version: Option<i64>) -> String {
+    version.map_or("?".to_string(), |v| v.to_string())
+}
+
+fn print_diff(diff: &SampleDiff) {
+    println!(
+        "Diff: {} added, {} updated, {} revoked, {} removed",
+        diff.added.len(),
+        diff.updated.len(),
+        diff.revoked.len(),
+        diff.removed.len()
+    );
+
+    for change in &diff.added {
+        println!(
+            "  + {} (version {})",
+            change.sample_id,
+            format_version(change.version)
+        );
+    }
+    for change in &diff.updated {
+        let status = change
+            .version_status
+            .as_deref()
+            .map(|s| format!(" [status: {}]", s))
+            .unwrap_or_default();
+        println!(
+            "  ~ {} (version {} -> {}){}",
+            change.sample_id,
+            format_version(change.previous_version),
+            format_version(change.version),
+            status
+        );
+    }
+    for change in &diff.revoked {
+        let comment = change
+            .version_comment
+            .as_deref()
+            .map(|c| format!(" - {}", c))
+            .unwrap_or_default();
+        println!("  x {} (revoked){}", change.sample_id, comment);
+    }
+    for sample_id in &diff.removed {
+        println!("  - {} (out of window)", sample_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(id: &str, version: i64) -> SampleData {
+        SampleData {
+            sample_id: Some(id.to_string()),
+            submitted_at_timestamp: 1751500000,
+            version: Some(version),
+            version_status: Some("LATEST_VERSION".to_string()),
+            version_comment: None,
+        }
+    }
+
+    fn inventory(entries: &[(&str, Option<i64>)]) -> BTreeMap<String, Option<i64>> {
+        entries
+            .iter()
+            .map(|(id, version)| (id.to_string(), *version))
+            .collect()
+    }
+
+    #[test]
+    fn test_compute_diff_categories() {
+        let inventory = inventory(&[
+            ("A1_10", Some(1)),
+            ("B2_10", Some(1)),
+            ("C3_10", Some(2)),
+            ("D4_10", Some(1)),
+        ]);
+        let upstream = vec![
+            sample("A1_10", 1),
+            sample("B2_10", 1),
+            sample("B2_10", 2),
+            sample("C3_10", 2),
+            sample("E5_10", 1),
+        ];
+        let mut revocation = sample("C3_10", 3);
+        revocation.version_status = Some("REVOKED".to_string());
+        revocation.version_comment = Some("contaminated run".to_string());
+
+        let diff = compute_diff(&inventory, &upstream, &[revocation]);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].sample_id, "E5_10");
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0].sample_id, "B2_10");
+        assert_eq!(diff.updated[0].previous_version, Some(1));
+        assert_eq!(diff.updated[0].version, Some(2));
+        assert_eq!(diff.revoked.len(), 1);
+        assert_eq!(
+            diff.revoked[0].version_comment.as_deref(),
+            Some("contaminated run")
+        );
+        assert_eq!(diff.removed, vec!["D4_10".to_string()]);
+    }
+
+    #[test]
+    fn test_compute_diff_identical_is_empty() {
+        let inventory = inventory(&[("A1_10", Some(1))]);
+        let diff = compute_diff(&inventory, &[sample("A1_10", 1)], &[]);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_revocations_outside_index_are_ignored() {
+        let diff = compute_diff(&BTreeMap::new(), &[], &[sample("Z9_10", 2)]);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_outdated_revocations_are_ignored() {
+        let inventory = inventory(&[("A1_10", Some(3)), ("B2_10", Some(1))]);
+        let mut revocations = vec![sample("A1_10", 2), sample("B2_10", 2)];
+        for revocation in &mut revocations {
+            revocation.version_status = Some("REVOKED".to_string());
+        }
+        // B2_10 was submitted again after its revocation
+        let upstream = vec![sample("A1_10", 3), sample("B2_10", 3)];
+
+        let diff = compute_diff(&inventory, &upstream, &revocations);
+
+        assert!(diff.revoked.is_empty());
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0].sample_id, "B2_10");
+        assert_eq!(diff.updated[0].version, Some(3));
+    }
+
+    #[test]
+    fn test_build_sample_list_body() {
+        let body = build_sample_list_body(&ChangeFilter::submissions(0, "2025-04-11"), 20_000);
+        assert_eq!(body["samplingDateFrom"], "2025-04-11");
+        assert_eq!(body["submittedAtTimestampFrom"], 0);
+        assert_eq!(body["limit"], SAMPLE_LIST_PAGE_SIZE);
+        assert_eq!(body["offset"], 20_000);
+        assert_eq!(body["orderBy"][0]["field"], "sampleId");
+        assert!(body["fields"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("version")));
+    }
+}
@@ -2,21 +2,50 @@
 //!
 //! Queries the LAPIS API to check if any new sequences have been submitted
 //! since the last successful pipeline run using submittedAtTimestampFrom.
+//! Only counts (aggregated endpoint) and the few most recent samples are
+//! requested, so the check costs the same however much new data arrived.
 //!
 //! Exit codes:
 //! - 0: New data available (pipeline should run)
 //! - 1: No new data (pipeline can skip)
 //! - 2: Error occurred
+//! - 3: Another run holds the lock (see `--lock-dir`)
+//! - 4: A timestamp failed the clock-skew checks; the state was not advanced
+//!   (see `clock.rs`)
+//!
+//! With `--watch`, the tool keeps polling instead and triggers an update once
+//! new submissions have settled (see `watch.rs`). With `--organisms` or
+//! `--config`, several organisms are checked concurrently (see `multi.rs`);
+//! the exit code then reports whether any of them has new data. With `--diff`,
+//! the upstream samples are compared with the inventory of the live index
+//! (see `diff.rs`); exit code 0 then means the index differs.
+//!
+//! With `--webhook-url`, detected changes and failures are also posted as
+//! JSON events (see `srsilo_common::notify`).
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use clap::Parser;
+use clock::{TimestampError, CLOCK_SKEW_EXIT_CODE, DEFAULT_MAX_CLOCK_SKEW};
 use reqwest::Client;
-use serde::Deserialize;
-use std::path::Path;
-use tokio::fs;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use srsilo_common::lock::{self, LockError, LOCK_HELD_EXIT_CODE};
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use srsilo_common::state::{
+    generate_run_id, quarantine_state, read_state, write_state, FetchWindow, PipelineState,
+    StateError, STATE_FORMAT_VERSION,
+};
+use std::path::PathBuf;
+
+mod clock;
+mod diff;
+mod multi;
+mod watch;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+const TOOL_VERSION: &str = concat!("check_new_data ", env!("CARGO_PKG_VERSION"));
+
 #[derive(Parser, Debug)]
 #[command(name = "check_new_data")]
 #[command(about = "Check if new genomic data is available from LAPIS API")]
@@ -26,11 +55,11 @@ struct Args {
     api_base_url: String,
 
     /// Organism/virus identifier for the API endpoint (e.g., "covid", "rsva", "rsvb")
-    /// This is appended to the API base URL: {api_base_url}/{organism}/sample/details
+    /// This is appended to the API base URL: {api_base_url}/{organism}/sample/aggregated
     #[arg(long, default_value = "covid")]
     organism: String,
 
-    /// Path to read last update timestamp from
+    /// Path to read the pipeline state (last update) from
     #[arg(long, default_value = ".last_update")]
     timestamp_file: String,
 
@@ -38,9 +67,161 @@ struct Args {
     #[arg(long, default_value = "90")]
     days_back: i64,
 
-    /// Path to write the maximum submittedAtTimestamp found (for pipeline use)
+    /// Path to write the pending state with the maximum submittedAtTimestamp found (for pipeline use)
     #[arg(long, default_value = ".next_timestamp")]
     output_timestamp_file: String,
+
+    /// Identifier of this pipeline run, recorded in the state (generated if omitted)
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Move a corrupted state file aside and proceed as a first run instead of failing
+    #[arg(long)]
+    recover_state: bool,
+
+    /// Report new data (exit 0) and write the pending state even if nothing changed
+    #[arg(long)]
+    force: bool,
+
+    /// Check submissions at or after this Unix timestamp or date (YYYY-MM-DD, UTC) instead
+    /// of after the checkpoint; the pending state is rebased onto it
+    #[arg(long, value_parser = parse_since, conflicts_with = "reset_state")]
+    since: Option<i64>,
+
+    /// Ignore the persisted state and check as on a first run (the state file is only
+    /// replaced once the pipeline promotes the pending state)
+    #[arg(long)]
+    reset_state: bool,
+
+    /// Organism base directory to lock against concurrent runs (no locking if omitted)
+    #[arg(long)]
+    lock_dir: Option<String>,
+
+    /// Keep polling the API and trigger an update once new submissions have settled
+    #[arg(
+        long,
+        conflicts_with_all = ["force", "since", "reset_state", "organisms", "config"]
+    )]
+    watch: bool,
+
+    /// Seconds between polls in watch mode
+    #[arg(long, default_value = "300")]
+    interval: u64,
+
+    /// Minutes without newer submissions before watch mode triggers (debounces upload batches)
+    #[arg(long, default_value = "30")]
+    quiet_minutes: u64,
+
+    /// Shell command to run when watch mode triggers
+    #[arg(long)]
+    on_change_command: Option<String>,
+
+    /// File to write the triggering max timestamp to when watch mode triggers
+    #[arg(long)]
+    trigger_file: Option<String>,
+
+    /// Check several organisms concurrently, with state files under {base_path}/{organism}/
+    #[arg(long, value_delimiter = ',')]
+    organisms: Vec<String>,
+
+    /// Read organisms (default: enabled_viruses), base path, API URL and windows from pipeline.yml
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Base directory holding one state directory per organism (multi-organism mode)
+    #[arg(long, default_value = ".")]
+    base_path: String,
+
+    /// Path to write the per-organism results as JSON (multi-organism mode)
+    #[arg(long)]
+    json_output: Option<String>,
+
+    /// List samples added, updated, revoked or removed compared to the live index
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "force", "since", "reset_state", "organisms", "config"]
+    )]
+    diff: bool,
+
+    /// Path to write the sample diff as JSON (diff mode)
+    #[arg(long)]
+    diff_output: Option<String>,
+
+    /// Seconds API timestamps may be ahead of the local clock before the check refuses
+    /// to advance the checkpoint
+    #[arg(long, default_value_t = DEFAULT_MAX_CLOCK_SKEW)]
+    max_clock_skew: i64,
+
+    /// URL to POST change summaries and errors to as JSON events
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
+}
+
+/// One organism to check, with its own state files.
+#[derive(Debug, Clone)]
+struct Target {
+    /// Virus key; names the organism's directory in multi-organism mode
+    name: String,
+    organism: String,
+    api_base_url: String,
+    days_back: i64,
+    timestamp_file: PathBuf,
+    output_timestamp_file: PathBuf,
+    lock_dir: Option<PathBuf>,
+    /// Tolerance in seconds for timestamps ahead of the local clock
+    max_clock_skew: i64,
+}
+
+impl Target {
+    /// The single organism described by the command line arguments.
+    fn from_args(args: &Args) -> Self {
+        Target {
+            name: args.organism.clone(),
+            organism: args.organism.clone(),
+            api_base_url: args.api_base_url.clone(),
+            days_back: args.days_back,
+            timestamp_file: PathBuf::from(&args.timestamp_file),
+            output_timestamp_file: PathBuf::from(&args.output_timestamp_file),
+            lock_dir: args.lock_dir.as_ref().map(PathBuf::from),
+            max_clock_skew: args.max_clock_skew,
+        }
+    }
+}
+
+/// Changes found for one organism.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct CheckResult {
+    has_data: bool,
+    /// Maximum submittedAtTimestamp of the changes (for updating the checkpoint)
+    max_timestamp: Option<i64>,
+    new_submissions: u64,
+    revocations: u64,
+    /// Most recent revocations, with the submitter's reason
+    revoked_samples: Vec<RevokedSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct RevokedSample {
+    sample_id: Option<String>,
+    version_comment: Option<String>,
+}
+
+impl CheckResult {
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} new submission(s), {} revocation(s)",
+            self.new_submissions, self.revocations
+        );
+        let comments: Vec<&str> = self
+            .revoked_samples
+            .iter()
+            .filter_map(|s| s.version_comment.as_deref())
+            .collect();
+        if !comments.is_empty() {
+            summary.push_str(&format!(" - revoked: {}", comments.join("; ")));
+        }
+        summary
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,12 +229,24 @@ struct ApiResponse {
     data: Vec<SampleData>,
 }
 
+#[derive(Deserialize, Debug)]
+struct AggregatedResponse {
+    data: Vec<AggregatedCount>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AggregatedCount {
+    count: u64,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct SampleData {
     sample_id: Option<String>,
     submitted_at_timestamp: i64,
     #[serde(default)]
+    version: Option<i64>,
+    #[serde(default)]
     version_status: Option<String>,
     #[serde(default)]
     version_comment: Option<String>,
@@ -61,7 +254,12 @@ struct SampleData {
 
 #[tokio::main]
 async fn main() {
-    let exit_code = match run().await {
+    let args = Args::parse();
+    let run_id = args.run_id.clone().unwrap_or_else(generate_run_id);
+    let notifier =
+        Notifier::new(args.webhook_url.as_deref(), "check_new_data").with_run_id(&run_id);
+
+    let exit_code = match run(&args, &run_id, &notifier).await {
         Ok(has_new_data) => {
             if has_new_data {
                 0 // New data available
@@ -71,129 +269,375 @@ async fn main() {
         }
         Err(e) => {
             eprintln!("Error: {}", e);
-            2 // Error
+            if let Some(LockError::Held { .. }) = e.downcast_ref::<LockError>() {
+                LOCK_HELD_EXIT_CODE
+            } else {
+                notifier.error_async(&e.to_string()).await;
+                if e.downcast_ref::<TimestampError>().is_some()
+                    || e.downcast_ref::<multi::ClockSkewFailure>().is_some()
+                {
+                    CLOCK_SKEW_EXIT_CODE
+                } else {
+                    2 // Error
+                }
+            }
         }
     };
 
     std::process::exit(exit_code);
 }
 
-async fn run() -> Result<bool> {
-    let args = Args::parse();
+async fn run(args: &Args, run_id: &str, notifier: &Notifier) -> Result<bool> {
+    if !args.organisms.is_empty() || args.config.is_some() {
+        return multi::run_multi(args, run_id, notifier).await;
+    }
+
+    let target = Target::from_args(args);
+    let notifier = notifier.clone().with_organism(&target.organism);
 
     println!("=== Checking for new data ===");
-    println!("API: {}", args.api_base_url);
-    println!("Organism: {}", args.organism);
+    println!("API: {}", target.api_base_url);
+    println!("Organism: {}", target.organism);
 
-    let last_update = read_last_update(&args.timestamp_file).await?;
+    if args.diff {
+        return diff::run_diff(args, &target).await;
+    }
 
-    match last_update {
-        Some(last_date) => {
-            println!("Last update: {}", last_date.format("%Y-%m-%d %H:%M:%S UTC"));
-            println!("Last update timestamp: {}", last_date.timestamp());
+    if args.watch {
+        // Only returns on invalid watch configuration
+        watch::run_watch(args, &target, &notifier).await?;
+        return Ok(false);
+    }
 
-            let (has_new_data, max_timestamp) = check_for_data_changes(&args, last_date).await?;
+    let result = check_once(
+        &Client::new(),
+        &target,
+        CheckOptions::from_args(args),
+        run_id,
+        &notifier,
+    )
+    .await?;
+    Ok(result.has_data)
+}
 
-            if has_new_data {
-                if let Some(max_ts) = max_timestamp {
-                    // Write the max timestamp to file for the pipeline to use
-                    fs::write(&args.output_timestamp_file, max_ts.to_string()).await?;
-                    let max_dt = DateTime::from_timestamp(max_ts, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                        .unwrap_or_else(|| max_ts.to_string());
-                    println!("Max submission timestamp: {} ({})", max_ts, max_dt);
-                    println!("Written to: {}", args.output_timestamp_file);
-                }
-                println!("✓ New data available!");
-                println!("  Pipeline should run to fetch and process new sequences.");
-            } else {
-                println!("• No new data found.");
-                println!("  Pipeline can skip this run.");
-            }
+/// How a single check treats the persisted state (`--recover-state`,
+/// `--force`, `--since`, `--reset-state`).
+///
+/// None of these modify `.last_update`; they only change which baseline is
+/// queried and what is written to `.next_timestamp`.
+#[derive(Debug, Clone, Copy, Default)]
+struct CheckOptions {
+    recover_state: bool,
+    /// Report new data and write the pending state even if nothing changed
+    force: bool,
+    /// Look for submissions at or after this timestamp instead of after the checkpoint
+    since: Option<i64>,
+    /// Ignore the persisted state, as on a first run
+    reset_state: bool,
+}
 
-            Ok(has_new_data)
+impl CheckOptions {
+    fn from_args(args: &Args) -> Self {
+        CheckOptions {
+            recover_state: args.recover_state,
+            force: args.force,
+            since: args.since,
+            reset_state: args.reset_state,
         }
-        None => {
-            println!("No previous update timestamp found - first run.");
-            println!("Creating initial timestamp from current time...");
+    }
+}
+
+/// Parses `--since` as a Unix timestamp or a date (midnight UTC).
+fn parse_since(value: &str) -> std::result::Result<i64, String> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp())
+        .map_err(|_| format!("expected a Unix timestamp or YYYY-MM-DD, got '{}'", value))
+}
+
+/// Checkpoint to write to the pending state.
+///
+/// New changes never move the checkpoint back behind the previous one, except
+/// when `--since` explicitly rebases it or `--reset-state` ignores it (then
+/// `previous_checkpoint` is `None`). Without any changes, a forced run keeps
+/// the saved checkpoint even under those flags, so the next run does not query
+/// the whole window again; the baseline is only used if nothing was saved.
+fn next_checkpoint(
+    max_timestamp: Option<i64>,
+    previous_checkpoint: Option<i64>,
+    saved_checkpoint: Option<i64>,
+    baseline: i64,
+) -> i64 {
+    match max_timestamp {
+        Some(max_timestamp) => previous_checkpoint.map_or(max_timestamp, |p| p.max(max_timestamp)),
+        None => saved_checkpoint.unwrap_or(baseline),
+    }
+}
+
+/// Runs a single check, writing the pending state if new data is available.
+async fn check_once(
+    client: &Client,
+    target: &Target,
+    options: CheckOptions,
+    run_id: &str,
+    notifier: &Notifier,
+) -> Result<CheckResult> {
+    let _lock = match &target.lock_dir {
+        Some(dir) => Some(lock::acquire(dir, "check_new_data")?),
+        None => None,
+    };
 
+    let previous_state = if options.reset_state {
+        println!(
+            "Ignoring state in {} (--reset-state); it is replaced once the pipeline succeeds.",
+            target.timestamp_file.display()
+        );
+        None
+    } else {
+        load_previous_state(target, options.recover_state)?
+    };
+
+    let saved_checkpoint = match &previous_state {
+        Some(state) => Some(state.last_processed_timestamp),
+        // An unreadable state is what --reset-state is for, so it is not an error here
+        None if options.reset_state => read_state(&target.timestamp_file)
+            .ok()
+            .flatten()
+            .map(|loaded| loaded.state.last_processed_timestamp),
+        None => None,
+    };
+
+    let now = Utc::now();
+    let (baseline, previous_checkpoint) = match (options.since, &previous_state) {
+        (Some(since), _) => {
+            println!("Checking submissions since {} (--since)", since);
+            // Changes are queried strictly after the baseline
+            (since - 1, None)
+        }
+        (None, Some(state)) => {
+            let last_date = DateTime::from_timestamp(state.last_processed_timestamp, 0)
+                .ok_or("Invalid timestamp in state file")?;
+            println!("Last update: {}", last_date.format("%Y-%m-%d %H:%M:%S UTC"));
+            println!("Last update timestamp: {}", last_date.timestamp());
+            println!("Last run: {} ({})", state.run_id, state.tool_version);
+            clock::check_checkpoint(
+                state.last_processed_timestamp,
+                now.timestamp(),
+                target.max_clock_skew,
+            )?;
+            (
+                state.last_processed_timestamp,
+                Some(state.last_processed_timestamp),
+            )
+        }
+        (None, None) => {
+            println!("No previous update timestamp found - first run.");
             // For first run, use a timestamp far enough in the past to catch recent data
             // but query the API to get the actual max timestamp
-            let initial_timestamp =
-                (Utc::now() - chrono::Duration::days(args.days_back)).timestamp();
-            let initial_date = DateTime::from_timestamp(initial_timestamp, 0)
-                .ok_or("Failed to create initial timestamp")?;
+            let initial_timestamp = (now - chrono::Duration::days(target.days_back)).timestamp();
+            (initial_timestamp, None)
+        }
+    };
 
-            println!(
-                "Querying from: {}",
-                initial_date.format("%Y-%m-%d %H:%M:%S UTC")
-            );
+    let baseline_date =
+        DateTime::from_timestamp(baseline, 0).ok_or("Invalid baseline timestamp")?;
+    println!(
+        "Querying from: {}",
+        baseline_date.format("%Y-%m-%d %H:%M:%S UTC")
+    );
 
-            let (has_new_data, max_timestamp) = check_for_data_changes(&args, initial_date).await?;
+    let fetch_window = previous_state
+        .as_ref()
+        .and_then(|s| s.fetch_window.as_ref());
+    let window_start = sampling_window_start(now, target.days_back, fetch_window);
+    let mut result = check_for_data_changes(client, target, baseline_date, window_start).await?;
 
-            if has_new_data {
-                if let Some(max_ts) = max_timestamp {
-                    fs::write(&args.output_timestamp_file, max_ts.to_string()).await?;
-                    let max_dt = DateTime::from_timestamp(max_ts, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                        .unwrap_or_else(|| max_ts.to_string());
-                    println!("Max submission timestamp: {} ({})", max_ts, max_dt);
-                    println!("Written to: {}", args.output_timestamp_file);
-                }
-                println!("✓ Data available - pipeline should fetch initial data.");
-            } else {
-                println!("• No data found in rolling window.");
-            }
+    if let Some(max_ts) = result.max_timestamp.filter(|_| result.has_data) {
+        clock::check_max_timestamp(
+            max_ts,
+            Some(baseline),
+            Utc::now().timestamp(),
+            target.max_clock_skew,
+        )?;
+    }
+
+    let found_changes = result.has_data;
+    if found_changes || options.force {
+        let max_ts = result.max_timestamp.filter(|_| found_changes);
+        // Write the pending state for the pipeline to promote on success
+        write_next_state(
+            target,
+            previous_state.as_ref(),
+            next_checkpoint(max_ts, previous_checkpoint, saved_checkpoint, baseline),
+            run_id,
+        )?;
+    }
+
+    if found_changes {
+        println!("✓ New data available!");
+        notifier
+            .changes_async(&result.summary(), serde_json::to_value(&result)?)
+            .await;
+        println!("  Pipeline should run to fetch and process new sequences.");
+    } else if options.force {
+        println!("• No new data found, but the run is forced (--force).");
+        result.has_data = true;
+    } else {
+        println!("• No new data found.");
+        println!("  Pipeline can skip this run.");
+    }
 
-            Ok(has_new_data)
+    Ok(result)
+}
+
+/// Reads the promoted state, migrating legacy files and handling corruption.
+///
+/// A corrupted state is an error unless `--recover-state` is given, in which
+/// case the file is moved aside and the run proceeds as a first run.
+fn load_previous_state(target: &Target, recover_state: bool) -> Result<Option<PipelineState>> {
+    let path = target.timestamp_file.as_path();
+
+    match read_state(path) {
+        Ok(Some(loaded)) => {
+            if loaded.migrated_from_legacy {
+                println!(
+                    "Migrating legacy timestamp file {} to state format v{}",
+                    path.display(),
+                    STATE_FORMAT_VERSION
+                );
+            }
+            Ok(Some(loaded.state))
+        }
+        Ok(None) => Ok(None),
+        Err(StateError::Corrupted { reason, .. }) if recover_state => {
+            let backup = quarantine_state(path)?;
+            println!(
+                "WARNING: State file {} is corrupted ({}), moved to {}",
+                path.display(),
+                reason,
+                backup.display()
+            );
+            Ok(None)
         }
+        Err(e) => Err(e.into()),
     }
 }
 
-async fn read_last_update(path: &str) -> Result<Option<DateTime<Utc>>> {
-    let file_path = Path::new(path);
+/// Writes the pending state for this run, carrying over the sample inventory
+/// of the previous state until `fetch_silo_data` records the new one.
+fn write_next_state(
+    target: &Target,
+    previous_state: Option<&PipelineState>,
+    max_ts: i64,
+    run_id: &str,
+) -> Result<()> {
+    let mut next_state = PipelineState::new(max_ts, TOOL_VERSION, run_id);
+    if let Some(previous) = previous_state {
+        next_state.samples = previous.samples.clone();
+        next_state.fetch_window = previous.fetch_window.clone();
+    }
+    write_state(&target.output_timestamp_file, &next_state)?;
 
-    if !file_path.exists() {
-        return Ok(None);
+    let max_dt = DateTime::from_timestamp(max_ts, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| max_ts.to_string());
+    println!("Max submission timestamp: {} ({})", max_ts, max_dt);
+    println!(
+        "Written to: {} (run {})",
+        target.output_timestamp_file.display(),
+        run_id
+    );
+    Ok(())
+}
+
+/// Number of most recent samples fetched per category, used for the max
+/// timestamp and the sample details shown in the log.
+const LATEST_SAMPLES_LIMIT: usize = 3;
+
+/// LAPIS filters selecting one category of changes since the last update.
+#[derive(Debug, Clone, PartialEq)]
+struct ChangeFilter {
+    submitted_at_timestamp_from: i64,
+    sampling_date_from: Option<String>,
+    is_revocation: bool,
+}
+
+impl ChangeFilter {
+    /// New submissions within the rolling window (revocations have no sampling date).
+    fn submissions(timestamp: i64, sampling_date_from: &str) -> Self {
+        ChangeFilter {
+            submitted_at_timestamp_from: timestamp,
+            sampling_date_from: Some(sampling_date_from.to_string()),
+            is_revocation: false,
+        }
     }
 
-    let content = fs::read_to_string(file_path).await?;
-    let timestamp = content.trim().parse::<i64>()?;
-    let datetime = DateTime::from_timestamp(timestamp, 0).ok_or("Invalid timestamp in file")?;
+    /// All revocations since the given timestamp.
+    fn revocations(timestamp: i64) -> Self {
+        ChangeFilter {
+            submitted_at_timestamp_from: timestamp,
+            sampling_date_from: None,
+            is_revocation: true,
+        }
+    }
 
-    Ok(Some(datetime))
+    /// Renders the filter as URL query parameters.
+    fn query_string(&self) -> String {
+        let mut query = format!(
+            "submittedAtTimestampFrom={}",
+            self.submitted_at_timestamp_from
+        );
+        if let Some(date) = &self.sampling_date_from {
+            query.push_str(&format!("&samplingDateFrom={}", date));
+        }
+        if self.is_revocation {
+            query.push_str("&isRevocation=true");
+        }
+        query
+    }
 }
 
-/// Builds the URL for fetching new submissions from the LAPIS API.
+/// Builds the URL counting the samples matching `filter` via the LAPIS aggregated endpoint.
 ///
 /// # Arguments
 /// * `api_base_url` - Base URL of the API (e.g., "https://api.db.wasap.genspectrum.org")
 /// * `organism` - Organism identifier (e.g., "covid", "rsva")
-/// * `timestamp` - Unix timestamp for submittedAtTimestampFrom filter
-/// * `sampling_date_from` - Date string (YYYY-MM-DD) for samplingDateFrom filter
-fn build_submissions_url(
-    api_base_url: &str,
-    organism: &str,
-    timestamp: i64,
-    sampling_date_from: &str,
-) -> String {
+/// * `filter` - Filters selecting the category of changes
+fn build_count_url(api_base_url: &str, organism: &str, filter: &ChangeFilter) -> String {
     format!(
-        "{}/{}/sample/details?submittedAtTimestampFrom={}&samplingDateFrom={}&dataFormat=JSON&downloadAsFile=false",
-        api_base_url, organism, timestamp, sampling_date_from
+        "{}/{}/sample/aggregated?{}&dataFormat=JSON&downloadAsFile=false",
+        api_base_url,
+        organism,
+        filter.query_string()
     )
 }
 
-/// Builds the URL for fetching revocations from the LAPIS API.
+/// Builds the URL of the LAPIS details endpoint.
 ///
-/// # Arguments
-/// * `api_base_url` - Base URL of the API
-/// * `organism` - Organism identifier
-/// * `timestamp` - Unix timestamp for submittedAtTimestampFrom filter
-fn build_revocations_url(api_base_url: &str, organism: &str, timestamp: i64) -> String {
-    format!(
-        "{}/{}/sample/details?submittedAtTimestampFrom={}&isRevocation=true&dataFormat=JSON&downloadAsFile=false",
-        api_base_url, organism, timestamp
-    )
+/// Queried with POST, as LAPIS only supports descending `orderBy` in request bodies.
+fn build_details_url(api_base_url: &str, organism: &str) -> String {
+    format!("{}/{}/sample/details", api_base_url, organism)
+}
+
+/// Builds the POST body requesting the `limit` most recently submitted samples
+/// matching `filter`, restricted to the fields needed for logging.
+fn build_latest_samples_body(filter: &ChangeFilter, limit: usize) -> Value {
+    let mut body = json!({
+        "submittedAtTimestampFrom": filter.submitted_at_timestamp_from,
+        "fields": ["sampleId", "submittedAtTimestamp", "versionStatus", "versionComment"],
+        "orderBy": [{"field": "submittedAtTimestamp", "type": "descending"}],
+        "limit": limit,
+        "dataFormat": "JSON",
+    });
+    if let Some(date) = &filter.sampling_date_from {
+        body["samplingDateFrom"] = json!(date);
+    }
+    if filter.is_revocation {
+        body["isRevocation"] = json!(true);
+    }
+    body
 }
 
 /// Calculates the maximum timestamp from an iterator of samples.
@@ -203,108 +647,166 @@ fn calculate_max_timestamp<'a>(samples: impl Iterator<Item = &'a SampleData>) ->
     samples.map(|s| s.submitted_at_timestamp).max()
 }
 
-/// Checks if there are any data changes (new submissions or revocations) after the given timestamp.
-///
-/// Makes two separate API calls:
-/// 1. New submissions within the rolling window (uses samplingDateFrom filter)  
-/// 2. All revocations since last update (revocations have no sampling date)
-///
-/// Returns `Ok((has_data, max_timestamp))` where:
-/// - has_data: true if any relevant changes found
-/// - max_timestamp: the maximum submittedAtTimestamp from the results (for updating the checkpoint)
-async fn check_for_data_changes(
-    args: &Args,
-    last_update: DateTime<Utc>,
-) -> Result<(bool, Option<i64>)> {
-    let client = Client::new();
-    // Use strictly greater than logic to avoid infinite loop on identical max timestamp
-    let timestamp = last_update.timestamp() + 1;
-
-    // Calculate the sampling date range (rolling window)
-    let now = Utc::now();
-    let sampling_date_from = (now - chrono::Duration::days(args.days_back))
-        .format("%Y-%m-%d")
-        .to_string();
-
-    println!(
-        "Querying API for changes after {}",
-        last_update.format("%Y-%m-%d %H:%M:%S UTC")
-    );
-    println!("  (submittedAtTimestampFrom: {})", timestamp);
-
-    // Call 1: Get new submissions within the rolling window
-    let submissions_url = build_submissions_url(
-        &args.api_base_url,
-        &args.organism,
-        timestamp,
-        &sampling_date_from,
-    );
+/// Summary of one category of changes: the total count and the most recent samples.
+#[derive(Debug)]
+struct ChangeSummary {
+    count: u64,
+    latest: Vec<SampleData>,
+}
 
-    println!(
-        "  Fetching new submissions in rolling window: {} to now ({} days)",
-        sampling_date_from, args.days_back
-    );
-    let submissions_response = client
-        .get(&submissions_url)
+/// Queries the total count and the most recent samples matching `filter`.
+///
+/// Costs two small requests regardless of how many samples match.
+async fn query_changes(
+    client: &Client,
+    target: &Target,
+    filter: &ChangeFilter,
+    label: &str,
+) -> Result<ChangeSummary> {
+    let count_url = build_count_url(&target.api_base_url, &target.organism, filter);
+    let count_response = client
+        .get(&count_url)
         .header("Accept", "application/json")
         .send()
         .await?;
 
-    if !submissions_response.status().is_success() {
+    if !count_response.status().is_success() {
         return Err(format!(
-            "New submissions API request failed: {}",
-            submissions_response.status()
+            "{} count API request failed: {}",
+            label,
+            count_response.status()
         )
         .into());
     }
 
-    let submissions_data: ApiResponse = submissions_response.json().await?;
+    let aggregated: AggregatedResponse = count_response.json().await?;
+    let count = aggregated.data.first().map(|c| c.count).unwrap_or(0);
 
-    // Call 2: Get all revocations since last update
-    let revocations_url = build_revocations_url(&args.api_base_url, &args.organism, timestamp);
+    if count == 0 {
+        return Ok(ChangeSummary {
+            count,
+            latest: Vec::new(),
+        });
+    }
 
-    println!("  Fetching revocations since last update");
-    let revocations_response = client
-        .get(&revocations_url)
+    let details_response = client
+        .post(build_details_url(&target.api_base_url, &target.organism))
         .header("Accept", "application/json")
+        .json(&build_latest_samples_body(filter, LATEST_SAMPLES_LIMIT))
         .send()
         .await?;
 
-    if !revocations_response.status().is_success() {
+    if !details_response.status().is_success() {
         return Err(format!(
-            "Revocations API request failed: {}",
-            revocations_response.status()
+            "{} API request failed: {}",
+            label,
+            details_response.status()
         )
         .into());
     }
 
-    let revocations_data: ApiResponse = revocations_response.json().await?;
+    let details: ApiResponse = details_response.json().await?;
+
+    Ok(ChangeSummary {
+        count,
+        latest: details.data,
+    })
+}
+
+/// First sampling date whose submissions can change the index.
+///
+/// This is the rolling window start, narrowed to the dates the last fetch
+/// actually included if it stopped early on the read budget.
+fn sampling_window_start(
+    now: DateTime<Utc>,
+    days_back: i64,
+    fetch_window: Option<&FetchWindow>,
+) -> NaiveDate {
+    let rolling_start = (now - chrono::Duration::days(days_back)).date_naive();
+    match fetch_window {
+        Some(window) => {
+            let start = window.effective_start(rolling_start);
+            if start > rolling_start {
+                println!(
+                    "Last fetch stopped on the read budget at {} - ignoring submissions before {}",
+                    window
+                        .read_budget_cutoff
+                        .map_or("-".to_string(), |d| d.to_string()),
+                    start
+                );
+            }
+            start
+        }
+        None => rolling_start,
+    }
+}
+
+/// Checks if there are any data changes (new submissions or revocations) after the given timestamp.
+///
+/// Queries two categories, each with a count and an ordered-limit request:
+/// 1. New submissions from `window_start` on (uses samplingDateFrom filter)
+/// 2. All revocations since last update (revocations have no sampling date)
+///
+/// Returns the change counts and the maximum submittedAtTimestamp from the results.
+async fn check_for_data_changes(
+    client: &Client,
+    target: &Target,
+    last_update: DateTime<Utc>,
+    window_start: NaiveDate,
+) -> Result<CheckResult> {
+    // Use strictly greater than logic to avoid infinite loop on identical max timestamp
+    let timestamp = last_update.timestamp() + 1;
+
+    let sampling_date_from = window_start.format("%Y-%m-%d").to_string();
+
+    println!(
+        "Querying API for changes after {}",
+        last_update.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    println!("  (submittedAtTimestampFrom: {})", timestamp);
+
+    // Category 1: New submissions within the rolling window
+    println!(
+        "  Counting new submissions in rolling window: {} to now ({} days configured)",
+        sampling_date_from, target.days_back
+    );
+    let submissions = query_changes(
+        client,
+        target,
+        &ChangeFilter::submissions(timestamp, &sampling_date_from),
+        "New submissions",
+    )
+    .await?;
+
+    // Category 2: All revocations since last update
+    println!("  Counting revocations since last update");
+    let revocations = query_changes(
+        client,
+        target,
+        &ChangeFilter::revocations(timestamp),
+        "Revocations",
+    )
+    .await?;
 
     // Combine and analyze results
-    let new_submissions_count = submissions_data.data.len();
-    let revocations_count = revocations_data.data.len();
-    let total_changes = new_submissions_count + revocations_count;
+    let total_changes = submissions.count + revocations.count;
     let has_data = total_changes > 0;
 
-    // Calculate max timestamp from both datasets (no cloning needed)
-    let max_timestamp = calculate_max_timestamp(
-        submissions_data
-            .data
-            .iter()
-            .chain(revocations_data.data.iter()),
-    );
+    // The latest samples of each category carry the max timestamp
+    let max_timestamp =
+        calculate_max_timestamp(submissions.latest.iter().chain(revocations.latest.iter()));
 
     // Log summary
-    if new_submissions_count > 0 {
+    if submissions.count > 0 {
         println!(
             "Found {} new submission(s) in rolling window (samplingDate: {} to now)",
-            new_submissions_count, sampling_date_from
+            submissions.count, sampling_date_from
         );
     }
-    if revocations_count > 0 {
+    if revocations.count > 0 {
         println!(
             "Found {} revocation(s) since last update (submittedAtTimestamp >= {})",
-            revocations_count, timestamp
+            revocations.count, timestamp
         );
     }
     if has_data {
@@ -313,24 +815,37 @@ async fn check_for_data_changes(
             total_changes
         );
 
-        // Log sample details (first few from each category)
-        log_sample_details(&submissions_data.data, "New submissions", false);
-        log_sample_details(&revocations_data.data, "Revocations", true);
+        // Log sample details (most recent from each category)
+        log_sample_details(&submissions, "New submissions", false);
+        log_sample_details(&revocations, "Revocations", true);
     } else {
         println!("No new submissions or revocations found");
     }
 
-    Ok((has_data, max_timestamp))
+    Ok(CheckResult {
+        has_data,
+        max_timestamp,
+        new_submissions: submissions.count,
+        revocations: revocations.count,
+        revoked_samples: revocations
+            .latest
+            .iter()
+            .map(|s| RevokedSample {
+                sample_id: s.sample_id.clone(),
+                version_comment: s.version_comment.clone(),
+            })
+            .collect(),
+    })
 }
 
 /// Helper function to log sample details in a consistent format
-fn log_sample_details(samples: &[SampleData], category: &str, is_revocation_category: bool) {
-    if samples.is_empty() {
+fn log_sample_details(summary: &ChangeSummary, category: &str, is_revocation_category: bool) {
+    if summary.latest.is_empty() {
         return;
     }
 
-    println!("  {} details:", category);
-    for (i, sample) in samples.iter().enumerate().take(3) {
+    println!("  {} details (most recent):", category);
+    for (i, sample) in summary.latest.iter().enumerate().take(LATEST_SAMPLES_LIMIT) {
         let sample_id = sample.sample_id.as_deref().unwrap_or("<unknown sample id>");
 
         if is_revocation_category {
@@ -355,8 +870,9 @@ fn log_sample_details(samples: &[SampleData], category: &str, is_revocation_cate
         }
     }
 
-    if samples.len() > 3 {
-        println!("    ... and {} more", samples.len() - 3);
+    let shown = summary.latest.len().min(LATEST_SAMPLES_LIMIT) as u64;
+    if summary.count > shown {
+        println!("    ... and {} more", summary.count - shown);
     }
 }
 
@@ -365,42 +881,155 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_build_submissions_url() {
-        let url =
-            build_submissions_url("https://api.example.org", "covid", 1700000000, "2024-01-01");
+    fn test_run_mode_conflicts() {
+        let parse = |extra: &[&str]| {
+            Args::try_parse_from(["check_new_data"].iter().chain(extra)).map(|_| ())
+        };
+        assert!(parse(&["--diff"]).is_ok());
+        assert!(parse(&["--watch", "--on-change-command", "true"]).is_ok());
+        for conflicting in [
+            &["--diff", "--watch"][..],
+            &["--diff", "--force"],
+            &["--watch", "--since", "2025-07-01"],
+            &["--watch", "--reset-state"],
+            &["--diff", "--organisms", "covid,rsva"],
+            &["--watch", "--config", "pipeline.yml"],
+        ] {
+            let error = parse(conflicting).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_build_count_url_submissions() {
+        let filter = ChangeFilter::submissions(1700000000, "2024-01-01");
+        let url = build_count_url("https://api.example.org", "covid", &filter);
         assert_eq!(
             url,
-            "https://api.example.org/covid/sample/details?submittedAtTimestampFrom=1700000000&samplingDateFrom=2024-01-01&dataFormat=JSON&downloadAsFile=false"
+            "https://api.example.org/covid/sample/aggregated?submittedAtTimestampFrom=1700000000&samplingDateFrom=2024-01-01&dataFormat=JSON&downloadAsFile=false"
         );
     }
 
     #[test]
-    fn test_build_submissions_url_rsva() {
-        let url = build_submissions_url(
-            "https://api.db.wasap.genspectrum.org",
-            "rsva",
-            1700000000,
-            "2024-06-15",
-        );
-        assert!(url.contains("/rsva/sample/details"));
+    fn test_build_count_url_submissions_rsva() {
+        let filter = ChangeFilter::submissions(1700000000, "2024-06-15");
+        let url = build_count_url("https://api.db.wasap.genspectrum.org", "rsva", &filter);
+        assert!(url.contains("/rsva/sample/aggregated"));
         assert!(url.contains("submittedAtTimestampFrom=1700000000"));
         assert!(url.contains("samplingDateFrom=2024-06-15"));
+        assert!(!url.contains("isRevocation"));
     }
 
     #[test]
-    fn test_build_revocations_url() {
-        let url = build_revocations_url("https://api.example.org", "covid", 1700000000);
+    fn test_build_count_url_revocations() {
+        let filter = ChangeFilter::revocations(1700000000);
+        let url = build_count_url("https://api.example.org", "covid", &filter);
         assert_eq!(
             url,
-            "https://api.example.org/covid/sample/details?submittedAtTimestampFrom=1700000000&isRevocation=true&dataFormat=JSON&downloadAsFile=false"
+            "https://api.example.org/covid/sample/aggregated?submittedAtTimestampFrom=1700000000&isRevocation=true&dataFormat=JSON&downloadAsFile=false"
+        );
+    }
+
+    #[test]
+    fn test_build_details_url_rsvb() {
+        let url = build_details_url("https://api.example.org", "rsvb");
+        assert_eq!(url, "https://api.example.org/rsvb/sample/details");
+    }
+
+    #[test]
+    fn test_build_latest_samples_body_submissions() {
+        let filter = ChangeFilter::submissions(1700000000, "2024-01-01");
+        let body = build_latest_samples_body(&filter, 3);
+        assert_eq!(body["submittedAtTimestampFrom"], 1700000000);
+        assert_eq!(body["samplingDateFrom"], "2024-01-01");
+        assert_eq!(body["limit"], 3);
+        assert_eq!(body["orderBy"][0]["field"], "submittedAtTimestamp");
+        assert_eq!(body["orderBy"][0]["type"], "descending");
+        assert!(body.get("isRevocation").is_none());
+    }
+
+    #[test]
+    fn test_build_latest_samples_body_revocations() {
+        let filter = ChangeFilter::revocations(1700000000);
+        let body = build_latest_samples_body(&filter, 1);
+        assert_eq!(body["isRevocation"], true);
+        assert!(body.get("samplingDateFrom").is_none());
+        assert_eq!(body["limit"], 1);
+    }
+
+    #[test]
+    fn test_parse_aggregated_response() {
+        let response: AggregatedResponse =
+            serde_json::from_str(r#"{"data":[{"count":12345}],"info":{}}"#).unwrap();
+        assert_eq!(response.data[0].count, 12345);
+    }
+
+    #[test]
+    fn test_sampling_window_start() {
+        let now = DateTime::parse_from_rfc3339("2025-07-10T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let date = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+        assert_eq!(sampling_window_start(now, 90, None), date(4, 11));
+
+        let mut window = FetchWindow {
+            start_date: date(7, 9),
+            earliest_allowed: date(4, 10),
+            earliest_included: Some(date(6, 1)),
+            read_budget_cutoff: Some(date(5, 31)),
+        };
+        assert_eq!(sampling_window_start(now, 90, Some(&window)), date(6, 1));
+
+        // Fetch covered the whole window
+        window.read_budget_cutoff = None;
+        assert_eq!(sampling_window_start(now, 90, Some(&window)), date(4, 11));
+    }
+
+    #[test]
+    fn test_check_result_summary_includes_revocation_comments() {
+        let result = CheckResult {
+            has_data: true,
+            max_timestamp: Some(1751500000),
+            new_submissions: 2,
+            revocations: 1,
+            revoked_samples: vec![
+                RevokedSample {
+                    sample_id: Some("A1_10".to_string()),
+                    version_comment: Some("wrong sample sheet".to_string()),
+                },
+                RevokedSample {
+                    sample_id: Some("B2_10".to_string()),
+                    version_comment: None,
+                },
+            ],
+        };
+        assert_eq!(
+            result.summary(),
+            "2 new submission(s), 1 revocation(s) - revoked: wrong sample sheet"
         );
     }
 
     #[test]
-    fn test_build_revocations_url_rsvb() {
-        let url = build_revocations_url("https://api.example.org", "rsvb", 1600000000);
-        assert!(url.contains("/rsvb/sample/details"));
-        assert!(url.contains("isRevocation=true"));
+    fn test_parse_since() {
+        assert_eq!(parse_since("1751500000"), Ok(1751500000));
+        assert_eq!(parse_since("2025-07-03"), Ok(1751500800));
+        assert!(parse_since("03.07.2025").is_err());
+    }
+
+    #[test]
+    fn test_next_checkpoint() {
+        // New changes advance the checkpoint
+        assert_eq!(next_checkpoint(Some(200), Some(100), Some(100), 100), 200);
+        // Changes older than the checkpoint never move it back
+        assert_eq!(next_checkpoint(Some(50), Some(100), Some(100), 100), 100);
+        // ... unless --since or --reset-state rebases it
+        assert_eq!(next_checkpoint(Some(50), None, Some(100), 41), 50);
+        // Forced run without changes keeps the checkpoint
+        assert_eq!(next_checkpoint(None, Some(100), Some(100), 100), 100);
+        // ... also with --since or --reset-state, instead of the earlier baseline
+        assert_eq!(next_checkpoint(None, None, Some(100), 41), 100);
+        // Without a saved checkpoint (first run) the baseline is used
+        assert_eq!(next_checkpoint(None, None, None, 42), 42);
     }
 
     #[test]
@@ -414,6 +1043,7 @@ mod tests {
         let samples = [SampleData {
             sample_id: Some("test1".to_string()),
             submitted_at_timestamp: 1700000000,
+            version: None,
             version_status: None,
             version_comment: None,
         }];
@@ -426,18 +1056,21 @@ mod tests {
             SampleData {
                 sample_id: Some("test1".to_string()),
                 submitted_at_timestamp: 1700000000,
+                version: None,
                 version_status: None,
                 version_comment: None,
             },
             SampleData {
                 sample_id: Some("test2".to_string()),
                 submitted_at_timestamp: 1700000500,
+                version: None,
                 version_status: None,
                 version_comment: None,
             },
             SampleData {
                 sample_id: Some("test3".to_string()),
                 submitted_at_timestamp: 1700000100,
+                version: None,
                 version_status: None,
                 version_comment: None,
             },
@@ -0,0 +1,403 @@
+//! Multi-organism mode: check several organisms concurrently in one invocation.
+//!
+//! Organisms come from `--organisms` or from the orchestrator's `pipeline.yml`
+//! (`--config`, defaulting to `enabled_viruses`). Each organism keeps its own
+//! state files and lock in `{base_path}/{name}/`, exactly as the single mode
+//! is invoked by the pipeline. Results are printed as a table and optionally
+//! written as JSON for the orchestrator.
+
+use crate::clock::TimestampError;
+use crate::{check_once, Args, CheckOptions, CheckResult, Result, Target};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use srsilo_common::lock::LockError;
+use srsilo_common::notify::Notifier;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
+
+/// Subset of `pipeline.yml` needed to check organisms.
+#[derive(Deserialize, Debug)]
+struct PipelineConfig {
+    base_path: PathBuf,
+    api_base_url: String,
+    enabled_viruses: Vec<String>,
+    viruses: BTreeMap<String, VirusConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VirusConfig {
+    organism: String,
+    fetch_days: i64,
+}
+
+/// Status of one organism's check.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    NewData,
+    NoNewData,
+    Locked,
+    /// API timestamps disagree with the local clock, see `clock`
+    ClockSkew,
+    Error,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::NewData => "new_data",
+            Status::NoNewData => "no_new_data",
+            Status::Locked => "locked",
+            Status::ClockSkew => "clock_skew",
+            Status::Error => "error",
+        }
+    }
+}
+
+/// Result row for one organism, as printed and written to the JSON output.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct OrganismReport {
+    name: String,
+    organism: String,
+    status: Status,
+    #[serde(flatten)]
+    result: CheckResult,
+    error: Option<String>,
+}
+
+/// Error of a multi-organism check in which every failed organism found a
+/// clock skew; exits with `CLOCK_SKEW_EXIT_CODE` like the single mode.
+#[derive(Debug)]
+pub struct ClockSkewFailure(String);
+
+impl fmt::Display for ClockSkewFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ClockSkewFailure {}
+
+#[derive(Serialize, Debug)]
+struct MultiReport<'a> {
+    run_id: &'a str,
+    results: &'a [OrganismReport],
+}
+
+/// Builds the state file paths for an organism directory.
+fn target_in(
+    base_path: &Path,
+    name: &str,
+    organism: &str,
+    api_base_url: &str,
+    days_back: i64,
+    max_clock_skew: i64,
+) -> Target {
+    let dir = base_path.join(name);
+    Target {
+        name: name.to_string(),
+        organism: organism.to_string(),
+        api_base_url: api_base_url.to_string(),
+        days_back,
+        timestamp_file: dir.join(".last_update"),
+        output_timestamp_file: dir.join(".next_timestamp"),
+        lock_dir: Some(dir),
+        max_clock_skew,
+    }
+}
+
+/// Resolves the organisms to check from `--config` and/or `--organisms`.
+fn resolve_targets(args: &Args) -> Result<Vec<Target>> {
+    let Some(config_path) = &args.config else {
+        let base_path = Path::new(&args.base_path);
+        return Ok(args
+            .organisms
+            .iter()
+            .map(|name| {
+                target_in(
+                    base_path,
+                    name,
+                    name,
+                    &args.api_base_url,
+                    args.days_back,
+                    args.max_clock_skew,
+                )
+            })
+            .collect());
+    };
+
+    let config: PipelineConfig = serde_yaml::from_str(&fs::read_to_string(config_path)?)
+        .map_err(|e| format!("Failed to parse {}: {}", config_path, e))?;
+
+    let names = if args.organisms.is_empty() {
+        &config.enabled_viruses
+    } else {
+        &args.organisms
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            let virus = config
+                .viruses
+                .get(name)
+                .ok_or_else(|| format!("Unknown virus '{}' in {}", name, config_path))?;
+            Ok(target_in(
+                &config.base_path,
+                name,
+                &virus.organism,
+                &config.api_base_url,
+                virus.fetch_days,
+                args.max_clock_skew,
+            ))
+        })
+        .collect()
+}
+
+/// Checks all organisms concurrently. Returns whether any organism has new data,
+/// or an error if any check failed (after reporting all results).
+pub async fn run_multi(args: &Args, run_id: &str, notifier: &Notifier) -> Result<bool> {
+    let targets = resolve_targets(args)?;
+    if targets.is_empty() {
+        return Err("No organisms to check".into());
+    }
+
+    println!(
+        "=== Checking for new data: {} organism(s) ===",
+        targets.len()
+    );
+
+    let client = Client::new();
+    let mut tasks = JoinSet::new();
+    for (index, target) in targets.iter().cloned().enumerate() {
+        let client = client.clone();
+        let options = CheckOptions::from_args(args);
+        let run_id = run_id.to_string();
+        let notifier = notifier.clone().with_organism(&target.organism);
+        tasks.spawn(async move {
+            let outcome = async {
+                if let Some(dir) = &target.lock_dir {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                check_once(&client, &target, options, &run_id, &notifier).await
+            }
+            .await
+            .map_err(|e| {
+                let status = match e.downcast_ref::<LockError>() {
+                    Some(LockError::Held { .. }) => Status::Locked,
+                    _ if e.downcast_ref::<TimestampError>().is_some() => Status::ClockSkew,
+                    _ => Status::Error,
+                };
+                (status, e.to_string())
+            });
+            (index, target, outcome)
+        });
+    }
+
+    let mut reports: Vec<Option<OrganismReport>> = vec![None; targets.len()];
+    while let Some(joined) = tasks.join_next().await {
+        let (index, target, outcome) = joined?;
+        reports[index] = Some(report_for(&target, outcome));
+    }
+    let reports: Vec<OrganismReport> = reports.into_iter().flatten().collect();
+
+    println!();
+    print!("{}", format_table(&reports));
+
+    if let Some(json_output) = &args.json_output {
+        let report = MultiReport {
+            run_id,
+            results: &reports,
+        };
+        fs::write(json_output, serde_json::to_string_pretty(&report)? + "\n")?;
+        println!("Results written to: {}", json_output);
+    }
+
+    let failed: Vec<String> = reports
+        .iter()
+        .filter(|r| matches!(r.status, Status::Error | Status::ClockSkew))
+        .map(|r| {
+            format!(
+                "{} ({})",
+                r.name,
+                r.error.as_deref().unwrap_or("unknown error")
+            )
+        })
+        .collect();
+    if !failed.is_empty() {
+        let message = format!("Check failed for: {}", failed.join(", "));
+        // Other errors take precedence over clock skew in the exit code
+        if reports.iter().any(|r| r.status == Status::Error) {
+            return Err(message.into());
+        }
+        return Err(ClockSkewFailure(message).into());
+    }
+
+    Ok(reports.iter().any(|r| r.status == Status::NewData))
+}
+
+/// Classifies the outcome of one organism's check.
+fn report_for(
+    target: &Target,
+    outcome: std::result::Result<CheckResult, (Status, String)>,
+) -> OrganismReport {
+    let (status, result, error) = match outcome {
+        Ok(result) if result.has_data => (Status::NewData, result, None),
+        Ok(result) => (Status::NoNewData, result, None),
+        Err((status, e)) => (status, CheckResult::default(), Some(e)),
+    };
+
+    OrganismReport {
+        name: target.name.clone(),
+        organism: target.organism.clone(),
+        status,
+        result,
+        error,
+    }
+}
+
+fn format_table(reports: &[OrganismReport]) -> String {
+    let name_width = reports
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("ORGANISM".len());
+
+    let mut table = format!(
+        "{:<name_width$}  {:<11}  {:>11}  {:>11}  {}\n",
+        "ORGANISM", "STATUS", "SUBMISSIONS", "REVOCATIONS", "MAX TIMESTAMP"
+    );
+    for report in reports {
+        table.push_str(&format!(
+            "{:<name_width$}  {:<11}  {:>11}  {:>11}  {}\n",
+            report.name,
+            report.status.as_str(),
+            report.result.new_submissions,
+            report.result.revocations,
+            report
+                .result
+                .max_timestamp
+                .map_or("-".to_string(), |ts| ts.to_string())
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_target(name: &str) -> Target {
+        target_in(Path::new("/opt/srsilo"), name, name, "http://api", 90, 300)
+    }
+
+    #[test]
+    fn test_target_in_uses_organism_directory() {
+        let target = target_in(
+            Path::new("/opt/srsilo"),
+            "flu_h1",
+            "flu-h1",
+            "http://api",
+            30,
+            600,
+        );
+        assert_eq!(target.organism, "flu-h1");
+        assert_eq!(target.max_clock_skew, 600);
+        assert_eq!(
+            target.timestamp_file,
+            PathBuf::from("/opt/srsilo/flu_h1/.last_update")
+        );
+        assert_eq!(
+            target.output_timestamp_file,
+            PathBuf::from("/opt/srsilo/flu_h1/.next_timestamp")
+        );
+        assert_eq!(target.lock_dir, Some(PathBuf::from("/opt/srsilo/flu_h1")));
+    }
+
+    #[test]
+    fn test_parse_pipeline_config() {
+        let yaml = r#"
+base_path: /opt/srsilo
+tools_path: /opt/srsilo/tools
+api_base_url: https://api.db.wasap.genspectrum.org
+retention_days: 7
+enabled_viruses:
+  - covid
+viruses:
+  covid:
+    organism: covid
+    instance_name: wise-sarsCoV2
+    fetch_days: 90
+    chunk_size: 1000000
+"#;
+        let config: PipelineConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.enabled_viruses, vec!["covid"]);
+        assert_eq!(config.viruses["covid"].fetch_days, 90);
+    }
+
+    #[test]
+    fn test_report_for_classifies_outcomes() {
+        let target = test_target("covid");
+        let new_data = CheckResult {
+            has_data: true,
+            max_timestamp: Some(1751500000),
+            new_submissions: 3,
+            revocations: 1,
+            ..Default::default()
+        };
+        assert_eq!(report_for(&target, Ok(new_data)).status, Status::NewData);
+        assert_eq!(
+            report_for(&target, Ok(CheckResult::default())).status,
+            Status::NoNewData
+        );
+
+        let skewed = report_for(&target, Err((Status::ClockSkew, "future".to_string())));
+        assert_eq!(
+            serde_json::to_value(&skewed).unwrap()["status"],
+            "clock_skew"
+        );
+
+        let locked = report_for(&target, Err((Status::Locked, "held".to_string())));
+        assert_eq!(locked.status, Status::Locked);
+        assert_eq!(locked.error.as_deref(), Some("held"));
+        assert_eq!(locked.result, CheckResult::default());
+    }
+
+    #[test]
+    fn test_format_table() {
+        let reports = vec![
+            report_for(
+                &test_target("covid"),
+                Ok(CheckResult {
+                    has_data: true,
+                    max_timestamp: Some(1751500000),
+                    new_submissions: 42,
+                    revocations: 0,
+                    ..Default::default()
+                }),
+            ),
+            report_for(&test_target("rsva"), Ok(CheckResult::default())),
+        ];
+        let table = format_table(&reports);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("ORGANISM  STATUS"));
+        assert!(lines[1].starts_with("covid     new_data"));
+        assert!(lines[1].ends_with("1751500000"));
+        assert!(lines[2].starts_with("rsva      no_new_data"));
+        assert!(lines[2].ends_with("-"));
+    }
+
+    #[test]
+    fn test_report_json_shape() {
+        let report = report_for(&test_target("covid"), Ok(CheckResult::default()));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "no_new_data");
+        assert_eq!(json["new_submissions"], 0);
+        assert!(json["max_timestamp"].is_null());
+    }
+}
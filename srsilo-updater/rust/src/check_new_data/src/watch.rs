@@ -0,0 +1,263 @@
+//! Watch mode: poll LAPIS and trigger an update once a burst of submissions has settled.
+//!
+//! Each poll runs the regular change check against the promoted state (or the
+//! last triggered timestamp, whichever is newer). The trigger fires once the
+//! maximum submission timestamp has stayed the same for `--quiet-minutes`, so
+//! a Loculus upload batch is processed as a whole. The lock is only held while
+//! polling, never while the trigger command runs, so the command can run the
+//! pipeline itself. Watch mode never writes the pending state; the pipeline's
+//! own check does that.
+
+use crate::{
+    check_for_data_changes, clock, load_previous_state, sampling_window_start, Args, Result, Target,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use srsilo_common::lock::{self, LockError};
+use srsilo_common::notify::Notifier;
+use std::time::{Duration, Instant};
+use tokio::{fs, process::Command, time};
+
+/// Outcome of a single poll.
+#[derive(Debug, PartialEq)]
+enum Poll {
+    /// Another run holds the lock; the poll was skipped
+    Skipped,
+    NoChanges,
+    /// Changes found, with the maximum submission timestamp
+    Changes(i64),
+}
+
+/// Tracks the maximum submission timestamp until it has been stable for the quiet period.
+#[derive(Debug)]
+struct Debouncer {
+    quiet_period: Duration,
+    pending: Option<(i64, Instant)>,
+}
+
+impl Debouncer {
+    fn new(quiet_period: Duration) -> Self {
+        Debouncer {
+            quiet_period,
+            pending: None,
+        }
+    }
+
+    /// Records the latest observed max timestamp (`None` if no changes).
+    ///
+    /// Returns the timestamp to trigger on once it has been stable for the quiet period.
+    fn observe(&mut self, max_timestamp: Option<i64>, now: Instant) -> Option<i64> {
+        let Some(max_ts) = max_timestamp else {
+            self.pending = None;
+            return None;
+        };
+
+        match self.pending {
+            Some((pending_ts, _)) if pending_ts == max_ts => {}
+            _ => self.pending = Some((max_ts, now)),
+        }
+
+        let (pending_ts, since) = self.pending?;
+        if now.duration_since(since) >= self.quiet_period {
+            self.pending = None;
+            Some(pending_ts)
+        } else {
+            None
+        }
+    }
+}
+
+/// Polls forever; only returns on invalid configuration.
+pub async fn run_watch(args: &Args, target: &Target, notifier: &Notifier) -> Result<()> {
+    if args.on_change_command.is_none() && args.trigger_file.is_none() {
+        return Err("--watch requires --on-change-command or --trigger-file".into());
+    }
+    if args.interval == 0 {
+        return Err("--interval must be at least 1 second".into());
+    }
+
+    println!(
+        "Watch mode: polling every {}s, triggering after {} quiet minute(s)",
+        args.interval, args.quiet_minutes
+    );
+
+    let mut debouncer = Debouncer::new(Duration::from_secs(args.quiet_minutes * 60));
+    let mut last_triggered: Option<i64> = None;
+    let client = Client::new();
+
+    loop {
+        let observed = match poll_once(&client, target, args.recover_state, last_triggered).await {
+            Ok(Poll::Skipped) => None,
+            Ok(Poll::NoChanges) => Some(None),
+            Ok(Poll::Changes(max_ts)) => Some(Some(max_ts)),
+            Err(e) => {
+                eprintln!("WARNING: Poll failed: {}", e);
+                None
+            }
+        };
+
+        if let Some(max_timestamp) = observed {
+            if let Some(max_ts) = max_timestamp {
+                println!("Latest submission timestamp: {}", max_ts);
+            }
+            if let Some(trigger_ts) = debouncer.observe(max_timestamp, Instant::now()) {
+                println!("Submissions settled at {} - triggering update", trigger_ts);
+                if let Err(e) = fire_trigger(args, trigger_ts).await {
+                    eprintln!("WARNING: Trigger failed: {}", e);
+                    notifier
+                        .error_async(&format!("Watch trigger failed: {}", e))
+                        .await;
+                }
+                last_triggered = Some(trigger_ts);
+            }
+        }
+
+        time::sleep(Duration::from_secs(args.interval)).await;
+    }
+}
+
+/// Runs one change check while holding the lock.
+async fn poll_once(
+    client: &Client,
+    target: &Target,
+    recover_state: bool,
+    last_triggered: Option<i64>,
+) -> Result<Poll> {
+    let _lock = match &target.lock_dir {
+        Some(dir) => match lock::acquire(dir, "check_new_data --watch") {
+            Ok(guard) => Some(guard),
+            Err(LockError::Held { owner, .. }) => {
+                println!("Lock held by {} - skipping this poll", owner);
+                return Ok(Poll::Skipped);
+            }
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+
+    let now = Utc::now();
+    let previous_state = load_previous_state(target, recover_state)?;
+    let state_ts = match &previous_state {
+        Some(state) => {
+            clock::check_checkpoint(
+                state.last_processed_timestamp,
+                now.timestamp(),
+                target.max_clock_skew,
+            )?;
+            state.last_processed_timestamp
+        }
+        None => (now - chrono::Duration::days(target.days_back)).timestamp(),
+    };
+    let fetch_window = previous_state
+        .as_ref()
+        .and_then(|s| s.fetch_window.as_ref());
+    let window_start = sampling_window_start(now, target.days_back, fetch_window);
+    let baseline = last_triggered.map_or(state_ts, |t| t.max(state_ts));
+    let baseline_date =
+        DateTime::from_timestamp(baseline, 0).ok_or("Invalid baseline timestamp")?;
+
+    let result = check_for_data_changes(client, target, baseline_date, window_start).await?;
+    match result.max_timestamp {
+        Some(max_ts) if result.has_data => {
+            clock::check_max_timestamp(
+                max_ts,
+                Some(baseline),
+                now.timestamp(),
+                target.max_clock_skew,
+            )?;
+            Ok(Poll::Changes(max_ts))
+        }
+        _ => Ok(Poll::NoChanges),
+    }
+}
+
+/// Writes the trigger file and/or runs the configured command.
+async fn fire_trigger(args: &Args, max_ts: i64) -> Result<()> {
+    if let Some(trigger_file) = &args.trigger_file {
+        fs::write(trigger_file, max_ts.to_string()).await?;
+        println!("Trigger written to: {}", trigger_file);
+    }
+
+    if let Some(command) = &args.on_change_command {
+        println!("Running: {}", command);
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("SRSILO_TRIGGER_TIMESTAMP", max_ts.to_string())
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(format!("command exited with {}", status).into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debouncer_waits_for_quiet_period() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_secs(600));
+
+        assert_eq!(debouncer.observe(Some(100), start), None);
+        assert_eq!(
+            debouncer.observe(Some(100), start + Duration::from_secs(300)),
+            None
+        );
+        assert_eq!(
+            debouncer.observe(Some(100), start + Duration::from_secs(600)),
+            Some(100)
+        );
+        // Triggered once only
+        assert_eq!(
+            debouncer.observe(Some(100), start + Duration::from_secs(900)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_debouncer_restarts_on_new_submissions() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_secs(600));
+
+        debouncer.observe(Some(100), start);
+        assert_eq!(
+            debouncer.observe(Some(200), start + Duration::from_secs(500)),
+            None
+        );
+        assert_eq!(
+            debouncer.observe(Some(200), start + Duration::from_secs(900)),
+            None
+        );
+        assert_eq!(
+            debouncer.observe(Some(200), start + Duration::from_secs(1100)),
+            Some(200)
+        );
+    }
+
+    #[test]
+    fn test_debouncer_resets_without_changes() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_secs(600));
+
+        debouncer.observe(Some(100), start);
+        assert_eq!(
+            debouncer.observe(None, start + Duration::from_secs(300)),
+            None
+        );
+        assert_eq!(
+            debouncer.observe(Some(100), start + Duration::from_secs(700)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_debouncer_zero_quiet_period_triggers_immediately() {
+        let mut debouncer = Debouncer::new(Duration::ZERO);
+        assert_eq!(debouncer.observe(Some(42), Instant::now()), Some(42));
+    }
+}
@@ -16,6 +16,9 @@ use chrono::{Duration, NaiveDate};
 use clap::Parser;
 use reqwest::Client;
 use serde::Deserialize;
+use srsilo_common::lock;
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use srsilo_common::state::{read_state, write_state, FetchWindow};
 use std::path::Path;
 use tokio::{fs, io::AsyncWriteExt, time};
 
@@ -48,6 +51,18 @@ struct Args {
     /// Organism/virus identifier for the API endpoint (e.g., "covid", "rsva", "rsvb")
     #[arg(long, default_value = "covid")]
     organism: String,
+
+    /// Pending state file (written by check_new_data) to record the fetched samples in
+    #[arg(long)]
+    state_file: Option<String>,
+
+    /// Organism base directory to lock against concurrent runs (no locking if omitted)
+    #[arg(long)]
+    lock_dir: Option<String>,
+
+    /// URL to POST an error event to as JSON if the fetch fails
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -62,6 +77,8 @@ struct SampleData {
     sampling_date: String,
     count_silo_reads: String,
     silo_reads: String,
+    #[serde(default)]
+    version: Option<i64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -88,15 +105,28 @@ struct FileToDownload {
     url: String,
     date: NaiveDate,
     read_count: u64,
+    version: Option<i64>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    run_fetch(&args).await
+    let notifier =
+        Notifier::new(args.webhook_url.as_deref(), "fetch_silo_data").with_organism(&args.organism);
+    notifier.report_panics();
+
+    let result = run_fetch(&args).await;
+    if let Err(e) = &result {
+        notifier.error_async(&e.to_string()).await;
+    }
+    result
 }
 
 async fn run_fetch(args: &Args) -> Result<()> {
+    let _lock = match &args.lock_dir {
+        Some(dir) => Some(lock::acquire_or_exit(Path::new(dir), "fetch_silo_data")?),
+        None => None,
+    };
     let client = Client::new();
 
     // Print starting banner
@@ -127,6 +157,7 @@ async fn run_fetch(args: &Args) -> Result<()> {
     println!();
 
     let mut current_date = start_date;
+    let mut read_budget_cutoff = None;
     let mut days_processed = 0;
     let mut consecutive_empty_days = 0;
     let total_days_to_check = (start_date - earliest_allowed).num_days() + 1;
@@ -179,6 +210,7 @@ async fn run_fetch(args: &Args) -> Result<()> {
 
             if stats.total_reads + date_reads > args.max_reads {
                 println!("   Would exceed read limit, stopping");
+                read_budget_cutoff = Some(current_date);
                 break;
             }
 
@@ -220,18 +252,55 @@ async fn run_fetch(args: &Args) -> Result<()> {
 
     println!();
     println!("Starting file downloads...");
-    download_all_files(&client, &all_files, &mut stats, &args.output_dir).await?;
+    let downloaded = download_all_files(&client, &all_files, &mut stats, &args.output_dir).await?;
+
+    if let Some(state_file) = &args.state_file {
+        let window = FetchWindow {
+            start_date,
+            earliest_allowed,
+            earliest_included: stats.earliest_date,
+            read_budget_cutoff,
+        };
+        record_fetch_results(Path::new(state_file), &downloaded, window)?;
+    }
 
     print_final_summary(&stats, &args.output_dir);
     Ok(())
 }
 
-async fn download_all_files(
+/// Records the downloaded samples and the fetched window in the pending state.
+fn record_fetch_results(
+    state_file: &Path,
+    downloaded: &[&FileToDownload],
+    window: FetchWindow,
+) -> Result<()> {
+    let mut state = read_state(state_file)?
+        .ok_or_else(|| format!("State file {} not found", state_file.display()))?
+        .state;
+
+    state.samples = downloaded
+        .iter()
+        .map(|f| (f.sample_id.clone(), f.version))
+        .collect();
+    state.fetch_window = Some(window);
+    write_state(state_file, &state)?;
+
+    println!(
+        "Recorded {} sample(s) in {}",
+        state.samples.len(),
+        state_file.display()
+    );
+    Ok(())
+}
+
+/// Downloads all files, returning those that were downloaded successfully.
+async fn download_all_files<'a>(
     client: &Client,
-    files: &[FileToDownload],
+    files: &'a [FileToDownload],
     stats: &mut ProcessingStats,
     output_dir: &str,
-) -> Result<()> {
+) -> Result<Vec<&'a FileToDownload>> {
+    let mut downloaded = Vec::new();
     for (i, file) in files.iter().enumerate() {
         let progress = ((i + 1) as f32 / files.len() as f32 * 100.0) as u32;
         println!(
@@ -247,6 +316,7 @@ async fn download_all_files(
                 stats.downloaded_files += 1;
                 let size_mb = bytes as f64 / 1024.0 / 1024.0;
                 println!("   Success: {:.1} MB (sample: {})", size_mb, file.sample_id);
+                downloaded.push(file);
             }
             Err(e) => {
                 stats.download_errors += 1;
@@ -256,7 +326,7 @@ async fn download_all_files(
 
         time::sleep(time::Duration::from_millis(100)).await;
     }
-    Ok(())
+    Ok(downloaded)
 }
 
 async fn download_single_file(
@@ -390,6 +460,7 @@ fn process_samples_for_date(
                 url: file.url,
                 date: actual_date,
                 read_count,
+                version: sample.version,
             });
         }
     }
@@ -485,6 +556,7 @@ mod tests {
                 count_silo_reads: "1000".to_string(),
                 silo_reads: r#"[{"name": "file1.ndjson.zst", "url": "http://example.com/file1"}]"#
                     .to_string(),
+                version: Some(1),
             },
             SampleData {
                 sample_id: "sample1".to_string(), // duplicate - this one should be kept
@@ -493,6 +565,7 @@ mod tests {
                 silo_reads:
                     r#"[{"name": "file1_v2.ndjson.zst", "url": "http://example.com/file1_v2"}]"#
                         .to_string(),
+                version: Some(2),
             },
             SampleData {
                 sample_id: "sample2".to_string(),
@@ -500,6 +573,7 @@ mod tests {
                 count_silo_reads: "500".to_string(),
                 silo_reads: r#"[{"name": "file2.ndjson.zst", "url": "http://example.com/file2"}]"#
                     .to_string(),
+                version: None,
             },
         ];
 
@@ -515,6 +589,7 @@ mod tests {
             sample1_file.read_count, 2000,
             "Deduplication should keep the last occurrence (read_count 2000, not 1000)"
         );
+        assert_eq!(sample1_file.version, Some(2));
 
         // Verify sample2 is also present
         let sample2_file = files.iter().find(|f| f.sample_id == "sample2").unwrap();
@@ -532,6 +607,7 @@ mod tests {
                 {"name": "file1b.ndjson.zst", "url": "http://example.com/file1b"}
             ]"#
             .to_string(),
+            version: None,
         }];
 
         let date = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
@@ -558,6 +634,7 @@ mod tests {
             count_silo_reads: "12345678".to_string(),
             silo_reads: r#"[{"name": "file1.ndjson.zst", "url": "http://example.com/file1"}]"#
                 .to_string(),
+            version: None,
         }];
 
         let date = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
@@ -0,0 +1,186 @@
+//! Merge benchmark on the sample reads in `srsilo-updater/tests/data`.
+//!
+//! Run with `cargo bench -p merge_sorted_chunks`. The sample reads are
+//! repeated into sorted chunks and merged with the release binary. The same
+//! chunks are then merged in-process twice with a heap over the chunk readers:
+//! once extracting only the sort key and writing lines verbatim, as the merge
+//! does now, and once parsing and re-serializing every record, as it did
+//! before. Only the second comparison isolates the effect of the change.
+
+use serde_json::Value;
+use srsilo_common::sort_key::{KeyExtractor, MissingKeyPolicy, SortKeySpec};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const SORT_FIELD: &str = "/main/offset";
+/// Copies of the sample reads to merge
+const COPIES: usize = 400;
+const CHUNKS: usize = 32;
+const ROUNDS: usize = 5;
+
+fn main() {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../tests/data");
+    let sample = read_sample_lines(&data_dir);
+    let lines: Vec<&str> = (0..COPIES)
+        .flat_map(|_| sample.iter().map(String::as_str))
+        .collect();
+    let bytes: usize = lines.iter().map(|line| line.len() + 1).sum();
+    println!(
+        "{} lines ({:.1} MB) in {} chunks",
+        lines.len(),
+        bytes as f64 / 1e6,
+        CHUNKS
+    );
+
+    let extractor =
+        KeyExtractor::new(vec![SortKeySpec::int(SORT_FIELD)], MissingKeyPolicy::Error).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let (chunk_paths, chunk_list) = write_chunks(&lines, &extractor, work_dir.path());
+
+    let merge = median(ROUNDS, |round| {
+        let tmp_dir = work_dir.path().join(format!("tmp_{}", round));
+        let status = Command::new(env!("CARGO_BIN_EXE_merge_sorted_chunks"))
+            .args(["--sort-field-path", SORT_FIELD, "--parallel-files", "8"])
+            .arg("--tmp-directory")
+            .arg(&tmp_dir)
+            .stdin(File::open(&chunk_list).unwrap())
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    });
+    println!(
+        "{:<30}{:>8.1} ms ({:.0} MB/s)",
+        "merge_sorted_chunks:",
+        ms(merge),
+        bytes as f64 / 1e6 / merge.as_secs_f64()
+    );
+
+    let raw = median(ROUNDS, |_| {
+        merge_chunks(&chunk_paths, bytes, |line| {
+            (extractor.extract(&line).unwrap().unwrap(), line)
+        });
+    });
+    let reparse = median(ROUNDS, |_| {
+        merge_chunks(&chunk_paths, bytes, |line| {
+            let value: Value = serde_json::from_str(&line).unwrap();
+            let key = value.pointer(SORT_FIELD).unwrap().as_i64().unwrap();
+            (key, value.to_string())
+        });
+    });
+    println!("{:<30}{:>8.1} ms", "heap merge, key only:", ms(raw));
+    println!(
+        "{:<30}{:>8.1} ms",
+        "heap merge, parse + serialize:",
+        ms(reparse)
+    );
+    println!(
+        "speedup of the merge: {:.1}x",
+        reparse.as_secs_f64() / raw.as_secs_f64()
+    );
+}
+
+/// The sample files have no trailing newline, so split on lines and drop empty ones.
+fn read_sample_lines(data_dir: &Path) -> Vec<String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(data_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".ndjson.zst"))
+        .collect();
+    files.sort();
+
+    let mut lines = Vec::new();
+    for file in files {
+        let mut content = String::new();
+        zstd::Decoder::new(File::open(file).unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        lines.extend(
+            content
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+    lines
+}
+
+/// Deals the lines round-robin into sorted chunks; returns the chunks and the
+/// chunk list file.
+fn write_chunks(
+    lines: &[&str],
+    extractor: &KeyExtractor,
+    work_dir: &Path,
+) -> (Vec<PathBuf>, PathBuf) {
+    let mut chunks: Vec<Vec<&str>> = vec![Vec::new(); CHUNKS];
+    for (i, line) in lines.iter().enumerate() {
+        chunks[i % CHUNKS].push(line);
+    }
+
+    let mut chunk_paths = Vec::new();
+    let mut list = String::new();
+    for (i, mut chunk) in chunks.into_iter().enumerate() {
+        chunk.sort_by_cached_key(|line| extractor.extract(line).unwrap().unwrap());
+        let path = work_dir.join(format!("chunk_{}.ndjson.zst", i));
+        let content: String = chunk.iter().map(|line| format!("{}\n", line)).collect();
+        fs::write(&path, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+        list.push_str(&format!("{}\n", path.display()));
+        chunk_paths.push(path);
+    }
+
+    let chunk_list = work_dir.join("chunks.list");
+    fs::write(&chunk_list, list).unwrap();
+    (chunk_paths, chunk_list)
+}
+
+/// Merges the chunks into memory with a min-heap over their readers, turning
+/// each line into its sort key and the text that is written out.
+fn merge_chunks<K: Ord>(
+    chunk_paths: &[PathBuf],
+    capacity: usize,
+    read: impl Fn(String) -> (K, String),
+) -> Vec<u8> {
+    let mut readers: Vec<_> = chunk_paths
+        .iter()
+        .map(|path| BufReader::new(zstd::Decoder::new(File::open(path).unwrap()).unwrap()).lines())
+        .collect();
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(line) = reader.next() {
+            let (key, text) = read(line.unwrap());
+            heap.push(Reverse((key, index, text)));
+        }
+    }
+
+    let mut output = Vec::with_capacity(capacity);
+    while let Some(Reverse((_, index, text))) = heap.pop() {
+        writeln!(output, "{}", text).unwrap();
+        if let Some(line) = readers[index].next() {
+            let (key, text) = read(line.unwrap());
+            heap.push(Reverse((key, index, text)));
+        }
+    }
+    output
+}
+
+fn median(rounds: usize, mut run: impl FnMut(usize)) -> Duration {
+    let mut times: Vec<Duration> = (0..rounds)
+        .map(|round| {
+            let start = Instant::now();
+            run(round);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[rounds / 2]
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
@@ -0,0 +1,291 @@
+//! Primary key deduplication of the final merge level.
+//!
+//! Records are compared while they stream out of the merge: duplicates are
+//! looked for among consecutive records with equal sort keys, which is where
+//! repeated copies of a record end up. Only the keys (and, for
+//! `keep-last-by-source`, the lines) of one such run are held in memory. The
+//! dedup key must be the last sort key (e.g. `/main/offset:int,/readId:string`),
+//! so that all copies of a record are in one run and a run holds only the
+//! copies of a single record.
+
+use serde::Serialize;
+use srsilo_common::sort_key::{SortKey, SortKeySpec};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// What to do with records whose dedup key was already written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// Fail the merge on the first duplicate
+    Error,
+    /// Keep the first copy
+    KeepFirst,
+    /// Keep the copy from the input file listed last
+    KeepLastBySource,
+}
+
+impl FromStr for DedupPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(DedupPolicy::Error),
+            "keep-first" => Ok(DedupPolicy::KeepFirst),
+            "keep-last-by-source" => Ok(DedupPolicy::KeepLastBySource),
+            _ => Err(format!(
+                "invalid dedup policy '{}' (expected error, keep-first or keep-last-by-source)",
+                value
+            )),
+        }
+    }
+}
+
+/// Checks that `pointer` is the last sort key. Otherwise copies of a record
+/// could be in different runs and be missed, or a run could be too large to buffer.
+pub fn check_dedup_key(pointer: &str, sort_keys: &[SortKeySpec]) -> Result<(), String> {
+    match sort_keys.last() {
+        Some(last) if last.pointer == pointer => Ok(()),
+        _ => Err(format!(
+            "the dedup key {} must be the last sort key (e.g. --sort-key <other keys>,{}:string)",
+            pointer, pointer
+        )),
+    }
+}
+
+/// One line of the `--duplicate-report` file.
+#[derive(Serialize)]
+struct DuplicateEntry<'a> {
+    /// The dedup key as it appears in the records
+    key: &'a serde_json::value::RawValue,
+    count: u64,
+}
+
+/// Counts for the log.
+#[derive(Debug, Default, PartialEq)]
+pub struct DedupStats {
+    /// Keys that occurred more than once
+    pub duplicate_keys: u64,
+    /// Records left out of the output
+    pub removed_records: u64,
+}
+
+/// Passes merged records on to `output`, leaving out duplicates.
+pub struct Deduplicator<W: Write> {
+    pointer: String,
+    policy: DedupPolicy,
+    output: W,
+    report: Option<BufWriter<File>>,
+    /// Sort key of the current run of equal keys
+    run_key: Option<SortKey>,
+    /// Dedup key to number of copies in the current run
+    counts: HashMap<String, u64>,
+    /// Buffered (dedup key, line) of the current run, for `keep-last-by-source`
+    lines: Vec<(String, String)>,
+    stats: DedupStats,
+}
+
+impl<W: Write> Deduplicator<W> {
+    pub fn new(
+        pointer: &str,
+        policy: DedupPolicy,
+        output: W,
+        report_path: Option<&Path>,
+    ) -> io::Result<Self> {
+        let report = match report_path {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None,
+        };
+        Ok(Deduplicator {
+            pointer: pointer.to_string(),
+            policy,
+            output,
+            report,
+            run_key: None,
+            counts: HashMap::new(),
+            lines: Vec::new(),
+            stats: DedupStats::default(),
+        })
+    }
+
+    /// Takes the next merged record with its raw dedup key, read in the same
+    /// pass as the sort key (see `KeyExtractor::with_extra_pointer`).
+    pub fn push(&mut self, sort_key: SortKey, key: Option<String>, line: String) -> io::Result<()> {
+        if self.run_key.as_ref() != Some(&sort_key) {
+            self.finish_run()?;
+            self.run_key = Some(sort_key);
+        }
+
+        let key = key.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Did not find dedup key {} in record", self.pointer),
+            )
+        })?;
+
+        let count = self.counts.entry(key.clone()).or_insert(0);
+        *count += 1;
+        let first_copy = *count == 1;
+
+        match self.policy {
+            DedupPolicy::Error if !first_copy => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Duplicate dedup key {} = {}", self.pointer, key),
+            )),
+            DedupPolicy::Error | DedupPolicy::KeepFirst => {
+                if first_copy {
+                    writeln!(self.output, "{}", line)?;
+                }
+                Ok(())
+            }
+            DedupPolicy::KeepLastBySource => {
+                self.lines.push((key, line));
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes out the rest and returns the counts.
+    pub fn finish(mut self) -> io::Result<DedupStats> {
+        self.finish_run()?;
+        self.output.flush()?;
+        if let Some(report) = self.report.as_mut() {
+            report.flush()?;
+        }
+        Ok(self.stats)
+    }
+
+    fn finish_run(&mut self) -> io::Result<()> {
+        // Later copies come from later input files: the merge is stable
+        let mut remaining = self.counts.clone();
+        for (key, line) in self.lines.drain(..) {
+            let left = remaining.get_mut(&key).unwrap();
+            *left -= 1;
+            if *left == 0 {
+                writeln!(self.output, "{}", line)?;
+            }
+        }
+
+        let mut duplicates: Vec<(&String, &u64)> =
+            self.counts.iter().filter(|(_, &count)| count > 1).collect();
+        duplicates.sort();
+        for (key, &count) in duplicates {
+            self.stats.duplicate_keys += 1;
+            self.stats.removed_records += count - 1;
+            if let Some(report) = self.report.as_mut() {
+                let entry = DuplicateEntry {
+                    key: serde_json::from_str(key)?,
+                    count,
+                };
+                serde_json::to_writer(&mut *report, &entry)?;
+                writeln!(report)?;
+            }
+        }
+        self.counts.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use srsilo_common::sort_key::{KeyExtractor, MissingKeyPolicy, SortKeySpec};
+
+    /// Runs the records, already in merge order, through a deduplicator keyed on `/id`.
+    fn dedup(lines: &[&str], policy: DedupPolicy) -> io::Result<(String, DedupStats)> {
+        let extractor = KeyExtractor::new(vec![SortKeySpec::int("/o")], MissingKeyPolicy::Error)
+            .unwrap()
+            .with_extra_pointer("/id");
+        let mut output = Vec::new();
+        let mut deduplicator = Deduplicator::new("/id", policy, &mut output, None)?;
+        for line in lines {
+            let (sort_key, key) = extractor.extract_with_extra(line).unwrap().unwrap();
+            let key = key.map(|raw| raw.get().to_string());
+            deduplicator.push(sort_key, key, line.to_string())?;
+        }
+        let stats = deduplicator.finish()?;
+        Ok((String::from_utf8(output).unwrap(), stats))
+    }
+
+    const LINES: [&str; 5] = [
+        r#"{"o":1,"id":"a","v":1}"#,
+        r#"{"o":1,"id":"b","v":1}"#,
+        r#"{"o":1,"id":"a","v":2}"#,
+        r#"{"o":2,"id":"a","v":3}"#,
+        r#"{"o":2,"id":"c","v":1}"#,
+    ];
+
+    #[test]
+    fn test_dedup_key_must_be_the_last_sort_key() {
+        let sort_keys: Vec<SortKeySpec> = ["/main/offset:int", "/readId:string"]
+            .iter()
+            .map(|k| k.parse().unwrap())
+            .collect();
+        assert!(check_dedup_key("/readId", &sort_keys).is_ok());
+        let error = check_dedup_key("/main/offset", &sort_keys).unwrap_err();
+        assert!(error.contains("must be the last sort key"));
+        assert!(check_dedup_key("/readId", &sort_keys[..1]).is_err());
+    }
+
+    #[test]
+    fn test_keep_first() {
+        let (output, stats) = dedup(&LINES, DedupPolicy::KeepFirst).unwrap();
+        assert_eq!(
+            output,
+            format!("{}\n{}\n{}\n{}\n", LINES[0], LINES[1], LINES[3], LINES[4])
+        );
+        assert_eq!(
+            stats,
+            DedupStats {
+                duplicate_keys: 1,
+                removed_records: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_keep_last_by_source() {
+        let (output, _) = dedup(&LINES, DedupPolicy::KeepLastBySource).unwrap();
+        assert_eq!(
+            output,
+            format!("{}\n{}\n{}\n{}\n", LINES[1], LINES[2], LINES[3], LINES[4])
+        );
+    }
+
+    #[test]
+    fn test_error_policy() {
+        let error = dedup(&LINES, DedupPolicy::Error).unwrap_err();
+        assert!(error.to_string().contains(r#"/id = "a""#));
+        assert!(dedup(&[LINES[0], LINES[3]], DedupPolicy::Error).is_ok());
+    }
+
+    #[test]
+    fn test_missing_dedup_key_is_an_error() {
+        assert!(dedup(&[r#"{"o":1}"#], DedupPolicy::KeepFirst).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("duplicates.ndjson");
+        let mut output = Vec::new();
+        let mut deduplicator =
+            Deduplicator::new("/id", DedupPolicy::KeepFirst, &mut output, Some(&path)).unwrap();
+        let extractor = KeyExtractor::new(vec![SortKeySpec::int("/o")], MissingKeyPolicy::Error)
+            .unwrap()
+            .with_extra_pointer("/id");
+        for line in [LINES[0], LINES[2], LINES[0]] {
+            let (sort_key, key) = extractor.extract_with_extra(line).unwrap().unwrap();
+            let key = key.map(|raw| raw.get().to_string());
+            deduplicator.push(sort_key, key, line.to_string()).unwrap();
+        }
+        deduplicator.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"key\":\"a\",\"count\":3}\n"
+        );
+    }
+}
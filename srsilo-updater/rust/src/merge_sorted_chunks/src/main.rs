@@ -1,35 +1,126 @@
+mod dedup;
+mod plan;
+#[cfg(test)]
+mod test_util;
+
 use clap::Parser;
+use dedup::{DedupPolicy, DedupStats, Deduplicator};
 use itertools::Itertools;
+use plan::PlanInput;
 use rayon::prelude::*;
-use serde_json::Value;
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use srsilo_common::size::format_size;
+use srsilo_common::sort_key::{self, KeyExtractor, MissingKeyPolicy, SortKey, SortKeySpec};
+use srsilo_common::{disk, lock};
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::fs::File;
 use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::channel;
-use std::{env, fs, thread};
+use std::{fs, thread};
 use zstd::stream::Decoder;
 use zstd::Encoder;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Sort key as `<json pointer>[:int|float|string|date[:asc|desc]]`; repeat or
+    /// separate with commas to break ties, e.g. `/main/offset:int,/readId:string`
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "sort_field_path",
+        conflicts_with = "sort_field_path"
+    )]
+    sort_key: Vec<SortKeySpec>,
+
+    /// Single ascending integer sort field (same as `--sort-key <path>:int`)
     #[arg(long)]
-    sort_field_path: String,
+    sort_field_path: Option<String>,
+
+    /// What to do with records whose sort key is missing or null:
+    /// error, first, last, drop or default=<value> (use the same in split_into_sorted_chunks)
+    #[arg(long, default_value = "error")]
+    missing_key: MissingKeyPolicy,
 
+    /// Directory for intermediate merge files; must be empty. Defaults to a
+    /// new directory in the system temp dir that is removed afterwards
     #[arg(long)]
     tmp_directory: Option<String>,
 
-    #[arg(long, default_value_t = 64)]
+    /// Files to merge at once; raised (up to 256) if fewer merge levels are
+    /// needed to fit into the free space of the tmp directory
+    #[arg(long, default_value_t = 64, value_parser = parse_parallel_files)]
     parallel_files: usize,
 
     #[arg(long)]
     num_threads: Option<usize>,
+
+    /// Write the merged records zstd-compressed to this file instead of to stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// zstd level for `--output`
+    #[arg(long, default_value_t = 3, requires = "output")]
+    compression_level: i32,
+
+    /// zstd worker threads for `--output` (defaults to the number of CPUs)
+    #[arg(long, requires = "output")]
+    compression_threads: Option<u32>,
+
+    /// Keep the intermediate `merged_chunks_*` files instead of removing each
+    /// once the next level has merged it (for debugging)
+    #[arg(long)]
+    keep_intermediate: bool,
+
+    /// Also remove the input chunks once they have been merged
+    #[arg(long)]
+    delete_chunks: bool,
+
+    /// Leave out records whose value at this JSON pointer (e.g. `/readId`) was
+    /// already written. Must be the last `--sort-key`, as duplicates are only
+    /// looked for among records with equal sort keys
+    #[arg(long)]
+    dedup_key: Option<String>,
+
+    /// What to do with duplicates: error, keep-first or keep-last-by-source
+    #[arg(long, default_value = "error", requires = "dedup_key")]
+    dedup_policy: DedupPolicy,
+
+    /// Write each duplicated key and its number of copies to this file as NDJSON
+    #[arg(long, requires = "dedup_key")]
+    duplicate_report: Option<PathBuf>,
+
+    /// Organism base directory to lock against concurrent runs (no locking if omitted)
+    #[arg(long)]
+    lock_dir: Option<String>,
+
+    /// URL to POST an error event to as JSON if the run fails
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    let notifier = Notifier::new(args.webhook_url.as_deref(), "merge_sorted_chunks");
+    notifier.report_panics();
+
+    let result = run(&args);
+    if let Err(e) = &result {
+        notifier.error(&e.to_string());
+    }
+    result
+}
+
+fn run(args: &Args) -> std::io::Result<()> {
+    let _lock = match &args.lock_dir {
+        Some(dir) => Some(lock::acquire_or_exit(
+            Path::new(dir),
+            "merge_sorted_chunks",
+        )?),
+        None => None,
+    };
 
     if let Some(num_threads) = args.num_threads {
         rayon::ThreadPoolBuilder::new()
@@ -38,25 +129,51 @@ fn main() -> std::io::Result<()> {
             .unwrap();
     }
 
-    let tmp_dir = if let Some(given_tmp_dir) = args.tmp_directory {
-        if Path::new(&given_tmp_dir).exists() {
-            assert_eq!(
-                fs::read_dir(&given_tmp_dir)?.count(),
-                0,
-                "The given tmp directory is not empty"
-            );
+    // Removed on drop, also when the run fails
+    let mut run_tmp_dir = None;
+    let tmp_dir = if let Some(given_tmp_dir) = &args.tmp_directory {
+        if Path::new(given_tmp_dir).exists() {
+            if fs::read_dir(given_tmp_dir)?.next().is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("The given tmp directory {} is not empty", given_tmp_dir),
+                ));
+            }
         } else {
-            fs::create_dir_all(&given_tmp_dir)?
+            fs::create_dir_all(given_tmp_dir)?
         };
         PathBuf::from(given_tmp_dir)
     } else {
-        env::temp_dir()
+        // A fresh directory per run, so concurrent merges never share file names
+        let dir = tempfile::Builder::new()
+            .prefix("merge_sorted_chunks_")
+            .tempdir()?;
+        let path = dir.path().to_path_buf();
+        if args.keep_intermediate {
+            eprintln!("Keeping intermediate files in {}", dir.keep().display());
+        } else {
+            run_tmp_dir = Some(dir);
+        }
+        path
     };
 
-    assert!(
-        args.parallel_files > 1,
-        "We need to work on at least 2 files in parallel."
-    );
+    let sort_keys = sort_key::resolve_sort_keys(&args.sort_key, args.sort_field_path.as_deref());
+    if let Some(pointer) = &args.dedup_key {
+        dedup::check_dedup_key(pointer, &sort_keys)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
+    let new_extractor = || {
+        KeyExtractor::new(sort_keys.clone(), args.missing_key.clone())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    };
+    // Only the first round reads the original chunks, so only its counts are reported
+    let extractor = new_extractor()?;
+    let later_rounds_extractor = new_extractor()?;
+    // The final level reads the dedup key in the same pass as the sort key
+    let final_extractor = match &args.dedup_key {
+        Some(pointer) => new_extractor()?.with_extra_pointer(pointer),
+        None => new_extractor()?,
+    };
 
     let reader = stdin();
 
@@ -64,93 +181,279 @@ fn main() -> std::io::Result<()> {
 
     let input_files_stdin = BufReader::new(reader)
         .lines()
-        .map(Result::unwrap)
-        .map(PathBuf::from);
+        .map(|line| line.map(PathBuf::from))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if input_files_stdin.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No input files received on stdin",
+        ));
+    }
+
+    // Fail before merging if the levels (or the output) cannot fit
+    let input_size = total_size(&input_files_stdin)?;
+    let output_in_tmp_fs = match &args.output {
+        Some(output) => {
+            let output_dir = output_dir(output);
+            let same_fs = fs::metadata(&output_dir)?.dev() == fs::metadata(&tmp_dir)?.dev();
+            if !same_fs {
+                disk::ensure_available(&output_dir, input_size, "the merged output")?;
+            }
+            same_fs
+        }
+        None => false,
+    };
+    let available = disk::available_space(&tmp_dir)?;
+    let merge_plan = plan::plan(&PlanInput {
+        chunks: input_files_stdin.len(),
+        input_size,
+        preferred_fan_in: args.parallel_files,
+        keep_intermediate: args.keep_intermediate,
+        output_in_tmp_fs,
+        available,
+    })
+    .map_err(|e| std::io::Error::new(std::io::ErrorKind::StorageFull, e))?;
+    eprintln!(
+        "Merging {} chunk(s) ({}) in {} level(s) of up to {} files, needing about {} of {} free in {}",
+        input_files_stdin.len(),
+        format_size(input_size),
+        merge_plan.levels,
+        merge_plan.fan_in,
+        format_size(merge_plan.tmp_space),
+        format_size(available),
+        tmp_dir.display()
+    );
+    let fan_in = merge_plan.fan_in;
 
     let mut input_files = merge_files_in_batches(
         input_files_stdin,
         &tmp_dir,
-        &args.sort_field_path,
-        args.parallel_files,
+        &extractor,
+        fan_in,
         merge_iteration,
+        args.delete_chunks,
     )?;
 
     merge_iteration += 1;
 
-    if input_files.is_empty() {
-        panic!("No input files received");
-    }
-
-    while input_files.len() > args.parallel_files {
+    while input_files.len() > fan_in {
         input_files = merge_files_in_batches(
             input_files,
             &tmp_dir,
-            &args.sort_field_path,
-            args.parallel_files,
+            &later_rounds_extractor,
+            fan_in,
             merge_iteration,
+            !args.keep_intermediate,
         )?;
         merge_iteration += 1;
     }
 
-    merge_files(input_files, &mut stdout().lock(), &args.sort_field_path)?;
+    let dedup = args.dedup_key.as_deref().map(|pointer| Dedup {
+        pointer,
+        policy: args.dedup_policy,
+        report: args.duplicate_report.as_deref(),
+    });
+
+    let final_inputs = input_files.clone();
+    let dedup_stats = match &args.output {
+        Some(output) => {
+            let threads = args
+                .compression_threads
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get() as u32));
+            merge_to_file(
+                input_files,
+                output,
+                &final_extractor,
+                dedup.as_ref(),
+                args.compression_level,
+                threads,
+            )?
+        }
+        None => merge_final(
+            input_files,
+            &mut stdout().lock(),
+            &final_extractor,
+            dedup.as_ref(),
+        )?,
+    };
+
+    if !args.keep_intermediate {
+        remove_files(&final_inputs)?;
+    }
+
+    if let Some(dir) = run_tmp_dir {
+        dir.close()?;
+    }
+
+    if let Some(summary) = extractor.summary() {
+        eprintln!("{}", summary);
+    }
+    if let Some(stats) = dedup_stats {
+        eprintln!(
+            "{} duplicate record(s) of {} key(s) left out",
+            stats.removed_records, stats.duplicate_keys
+        );
+    }
 
     Ok(())
 }
 
-fn merge_files_in_batches<I>(
-    input_files: I,
+/// Merges consecutive runs of `batch_size` files in parallel.
+///
+/// Batch `n` always holds the same input files and the merged files are
+/// returned in batch order, so the next round sees the input order unchanged.
+/// With `remove_inputs`, each batch's files are removed once it is merged.
+fn merge_files_in_batches(
+    input_files: Vec<PathBuf>,
     tmp_dir: &Path,
-    sort_field_path: &str,
+    extractor: &KeyExtractor,
     batch_size: usize,
     merge_iteration: usize,
-) -> std::io::Result<Vec<PathBuf>>
-where
-    I: IntoIterator<Item = PathBuf> + Send + 'static,
-    I::IntoIter: Iterator<Item = PathBuf> + Send,
-{
-    let (tx, rx) = channel();
-
-    thread::spawn(move || {
-        for (batch_id, batch) in input_files
-            .into_iter()
-            .chunks(batch_size)
-            .into_iter()
-            .enumerate()
-        {
-            tx.send((batch_id, batch.collect::<Vec<PathBuf>>()))
-                .unwrap()
-        }
-    });
+    remove_inputs: bool,
+) -> std::io::Result<Vec<PathBuf>> {
+    let batches: Vec<Vec<PathBuf>> = input_files
+        .into_iter()
+        .chunks(batch_size)
+        .into_iter()
+        .map(|batch| batch.collect())
+        .collect();
 
-    rx.into_iter()
-        .par_bridge()
+    batches
+        .into_par_iter()
+        .enumerate()
         .map(|(batch_id, batch)| -> std::io::Result<PathBuf> {
             let file_name = tmp_dir.join(format!(
                 "merged_chunks_{}_{}.ndjson.zst",
                 merge_iteration, batch_id
             ));
 
-            let file = File::create(file_name.clone()).unwrap();
+            let file = File::create(&file_name).map_err(|e| with_path(e, &file_name))?;
             let mut encoder = Encoder::new(file, 3)?;
-            merge_files(batch, &mut encoder, sort_field_path)?;
+            merge_files(batch.iter().cloned(), &mut encoder, extractor)?;
             encoder.finish()?;
+            if remove_inputs {
+                remove_files(&batch)?;
+            }
 
             Ok(file_name)
         })
         .collect()
 }
 
-// Wrapper struct to allow sorting JSON values in a min-heap
+/// Parses `--parallel-files`; merging needs at least two files at once.
+fn parse_parallel_files(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(files) if files >= 2 => Ok(files),
+        Ok(_) => Err("need to merge at least 2 files at once".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Combined size of the files in bytes.
+fn total_size(files: &[PathBuf]) -> std::io::Result<u64> {
+    files
+        .iter()
+        .map(|file| Ok(fs::metadata(file).map_err(|e| with_path(e, file))?.len()))
+        .sum()
+}
+
+/// Directory the output file is written to.
+fn output_dir(output: &Path) -> PathBuf {
+    match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Adds the path to an I/O error, e.g. for a full disk while creating a file.
+fn with_path(error: std::io::Error, path: &Path) -> std::io::Error {
+    std::io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+/// Removes merge inputs that have been consumed.
+fn remove_files(files: &[PathBuf]) -> std::io::Result<()> {
+    for file in files {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+/// Merges into a zstd file, written atomically (temp file then rename) so a
+/// partial file never appears under the final name.
+fn merge_to_file(
+    files: Vec<PathBuf>,
+    output: &Path,
+    extractor: &KeyExtractor,
+    dedup: Option<&Dedup>,
+    level: i32,
+    threads: u32,
+) -> std::io::Result<Option<DedupStats>> {
+    let temp_path = output.with_extension("tmp");
+    let result = (|| {
+        let file = File::create(&temp_path)?;
+        let mut encoder = Encoder::new(file, level)?;
+        encoder.multithread(threads)?;
+        let stats = merge_final(files, &mut encoder, extractor, dedup)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&temp_path, output)?;
+        Ok(stats)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// `--dedup-key` settings, applied to the final merge level only.
+struct Dedup<'a> {
+    pointer: &'a str,
+    policy: DedupPolicy,
+    report: Option<&'a Path>,
+}
+
+/// The final merge level: like `merge_files`, but leaves out duplicates if
+/// `dedup` is set. `extractor` then reads the dedup key as its extra pointer.
+fn merge_final<W: Write>(
+    files: Vec<PathBuf>,
+    output: &mut W,
+    extractor: &KeyExtractor,
+    dedup: Option<&Dedup>,
+) -> std::io::Result<Option<DedupStats>> {
+    let Some(dedup) = dedup else {
+        return merge_files(files, output, extractor).map(|_| None);
+    };
+    let mut deduplicator = Deduplicator::new(
+        dedup.pointer,
+        dedup.policy,
+        BufWriter::new(output),
+        dedup.report,
+    )?;
+    merge_records(files, extractor, |entry| {
+        deduplicator.push(entry.sort_field, entry.extra, entry.line)
+    })?;
+    deduplicator.finish().map(Some)
+}
+
+// Wrapper struct to allow sorting lines by their key in a min-heap.
+// Equal keys are taken from the earlier input file first, so the merge is stable.
+// The line is written out verbatim, so records are never parsed in full.
 #[derive(Eq, PartialEq, Debug)]
 struct HeapEntry {
-    sort_field: i64,
-    value: Value,
+    sort_field: SortKey,
+    /// Raw value at the extractor's extra pointer (the dedup key)
+    extra: Option<String>,
+    line: String,
     index: usize,
 }
 
 impl Ord for HeapEntry {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.sort_field.cmp(&self.sort_field) // Reverse order to make BinaryHeap a min-heap
+        // Reverse order to make BinaryHeap a min-heap
+        other
+            .sort_field
+            .cmp(&self.sort_field)
+            .then_with(|| other.index.cmp(&self.index))
     }
 }
 
@@ -160,56 +463,73 @@ impl PartialOrd for HeapEntry {
     }
 }
 
-/// Extract the sort field value from a JSON object using a JSON pointer path.
-/// Returns the i64 value at the specified path.
-fn extract_sort_field(json: &Value, sort_field_path: &str) -> i64 {
-    json.pointer(sort_field_path)
-        .unwrap_or_else(|| panic!("Did not find field {sort_field_path} in object {json}"))
-        .as_i64()
-        .unwrap_or_else(|| panic!("the specified sort_column is not of type i64: {}", json))
+/// Reads the next record of a chunk that is not dropped by the missing key policy.
+fn next_entry<R: BufRead>(
+    lines: &mut std::io::Lines<R>,
+    index: usize,
+    extractor: &KeyExtractor,
+) -> std::io::Result<Option<HeapEntry>> {
+    for line in lines {
+        let line = line?;
+        if let Some((sort_field, extra)) = extractor.extract_with_extra(&line)? {
+            let extra = extra.map(|raw| raw.get().to_string());
+            return Ok(Some(HeapEntry {
+                sort_field,
+                extra,
+                line,
+                index,
+            }));
+        }
+    }
+    Ok(None)
 }
 
 // Merging function that reads from readers and writes to any object implementing `Write`
-fn merge_files<I, W: Write>(files: I, output: &mut W, sort_field_path: &str) -> std::io::Result<()>
+fn merge_files<I, W: Write>(
+    files: I,
+    output: &mut W,
+    extractor: &KeyExtractor,
+) -> std::io::Result<()>
 where
     I: IntoIterator<Item = PathBuf>,
 {
-    let mut heap = BinaryHeap::new();
+    let mut writer = BufWriter::new(output);
+    merge_records(files, extractor, |entry| writeln!(writer, "{}", entry.line))?;
+    writer.flush()
+}
 
-    let sorted_readers = files
-        .into_iter()
-        .map(|f| BufReader::new(Decoder::new(File::open(f).unwrap()).unwrap()));
+/// Merges the sorted files, passing each record to `emit` in order.
+fn merge_records<I>(
+    files: I,
+    extractor: &KeyExtractor,
+    mut emit: impl FnMut(HeapEntry) -> std::io::Result<()>,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let mut heap = BinaryHeap::new();
 
     // Store an iterator for each reader
-    let mut reader_iters: Vec<_> = sorted_readers.into_iter().map(|r| r.lines()).collect();
+    let mut reader_iters = files
+        .into_iter()
+        .map(|f| {
+            let file = File::open(&f).map_err(|e| with_path(e, &f))?;
+            Ok(BufReader::new(Decoder::new(file)?).lines())
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
 
     // Initialize heap with the first line from each reader
     for (index, iter) in reader_iters.iter_mut().enumerate() {
-        if let Some(Ok(line)) = iter.next() {
-            let json: Value = serde_json::from_str(&line)?;
-            heap.push(HeapEntry {
-                sort_field: extract_sort_field(&json, sort_field_path),
-                value: json,
-                index,
-            });
+        if let Some(entry) = next_entry(iter, index, extractor)? {
+            heap.push(entry);
         }
     }
 
-    let mut writer = BufWriter::new(output);
-    while let Some(HeapEntry {
-        sort_field: _sort_field,
-        value,
-        index,
-    }) = heap.pop()
-    {
-        writeln!(writer, "{}", value)?;
-        if let Some(Ok(line)) = reader_iters[index].next() {
-            let json: Value = serde_json::from_str(&line)?;
-            heap.push(HeapEntry {
-                sort_field: extract_sort_field(&json, sort_field_path),
-                value: json,
-                index,
-            });
+    while let Some(entry) = heap.pop() {
+        let index = entry.index;
+        emit(entry)?;
+        if let Some(entry) = next_entry(&mut reader_iters[index], index, extractor)? {
+            heap.push(entry);
         }
     }
 
@@ -219,22 +539,53 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
+    use crate::test_util::{read_zstd, write_chunks, write_zstd};
+    use serde_json::{json, Value};
+    use srsilo_common::sort_key::{KeyField, KeyValue};
     use std::cmp::Ordering;
 
+    fn int_key(value: i64) -> SortKey {
+        SortKey(vec![KeyField::Asc(KeyValue::Int(value))])
+    }
+
+    fn extractor(sort_keys: &[&str], policy: &str) -> KeyExtractor {
+        let sort_keys = sort_keys.iter().map(|k| k.parse().unwrap()).collect();
+        KeyExtractor::new(sort_keys, policy.parse().unwrap()).unwrap()
+    }
+
+    fn extract(json: &Value, sort_field_path: &str) -> std::io::Result<SortKey> {
+        let extractor = KeyExtractor::new(
+            vec![SortKeySpec::int(sort_field_path)],
+            MissingKeyPolicy::Error,
+        )
+        .unwrap();
+        Ok(extractor.extract(&json.to_string())?.unwrap())
+    }
+
+    // ==================== argument tests ====================
+
+    #[test]
+    fn test_parse_parallel_files() {
+        assert_eq!(parse_parallel_files("2"), Ok(2));
+        assert!(parse_parallel_files("1").is_err());
+        assert!(parse_parallel_files("-3").is_err());
+    }
+
     // ==================== HeapEntry ordering tests ====================
 
     #[test]
     fn test_heap_entry_ordering_min_heap() {
         // HeapEntry uses reversed ordering to create a min-heap from BinaryHeap
         let entry1 = HeapEntry {
-            sort_field: 10,
-            value: json!({"id": 1}),
+            sort_field: int_key(10),
+            extra: None,
+            line: json!({"id": 1}).to_string(),
             index: 0,
         };
         let entry2 = HeapEntry {
-            sort_field: 20,
-            value: json!({"id": 2}),
+            sort_field: int_key(20),
+            extra: None,
+            line: json!({"id": 2}).to_string(),
             index: 1,
         };
 
@@ -247,17 +598,21 @@ mod tests {
     #[test]
     fn test_heap_entry_equal_sort_fields() {
         let entry1 = HeapEntry {
-            sort_field: 100,
-            value: json!({"id": 1}),
+            sort_field: int_key(100),
+            extra: None,
+            line: json!({"id": 1}).to_string(),
             index: 0,
         };
         let entry2 = HeapEntry {
-            sort_field: 100,
-            value: json!({"id": 2}),
+            sort_field: int_key(100),
+            extra: None,
+            line: json!({"id": 2}).to_string(),
             index: 1,
         };
 
-        assert_eq!(entry1.cmp(&entry2), Ordering::Equal);
+        // Ties go to the earlier input file
+        assert_eq!(entry1.cmp(&entry2), Ordering::Greater);
+        assert_eq!(entry1.cmp(&entry1), Ordering::Equal);
     }
 
     #[test]
@@ -265,33 +620,36 @@ mod tests {
         let mut heap = BinaryHeap::new();
 
         heap.push(HeapEntry {
-            sort_field: 30,
-            value: json!({"ts": 30}),
+            sort_field: int_key(30),
+            extra: None,
+            line: json!({"ts": 30}).to_string(),
             index: 0,
         });
         heap.push(HeapEntry {
-            sort_field: 10,
-            value: json!({"ts": 10}),
+            sort_field: int_key(10),
+            extra: None,
+            line: json!({"ts": 10}).to_string(),
             index: 1,
         });
         heap.push(HeapEntry {
-            sort_field: 20,
-            value: json!({"ts": 20}),
+            sort_field: int_key(20),
+            extra: None,
+            line: json!({"ts": 20}).to_string(),
             index: 2,
         });
 
         // Should pop in ascending order (min-heap behavior)
-        assert_eq!(heap.pop().unwrap().sort_field, 10);
-        assert_eq!(heap.pop().unwrap().sort_field, 20);
-        assert_eq!(heap.pop().unwrap().sort_field, 30);
+        assert_eq!(heap.pop().unwrap().sort_field, int_key(10));
+        assert_eq!(heap.pop().unwrap().sort_field, int_key(20));
+        assert_eq!(heap.pop().unwrap().sort_field, int_key(30));
     }
 
-    // ==================== extract_sort_field tests ====================
+    // ==================== sort key extraction tests ====================
 
     #[test]
     fn test_extract_sort_field_top_level() {
         let json = json!({"timestamp": 1234567890, "name": "test"});
-        assert_eq!(extract_sort_field(&json, "/timestamp"), 1234567890);
+        assert_eq!(extract(&json, "/timestamp").unwrap(), int_key(1234567890));
     }
 
     #[test]
@@ -304,28 +662,196 @@ mod tests {
             }
         });
         assert_eq!(
-            extract_sort_field(&json, "/metadata/created/timestamp"),
-            9876543210
+            extract(&json, "/metadata/created/timestamp").unwrap(),
+            int_key(9876543210)
         );
     }
 
     #[test]
     fn test_extract_sort_field_negative_value() {
         let json = json!({"sort_key": -500});
-        assert_eq!(extract_sort_field(&json, "/sort_key"), -500);
+        assert_eq!(extract(&json, "/sort_key").unwrap(), int_key(-500));
     }
 
     #[test]
-    #[should_panic(expected = "Did not find field")]
     fn test_extract_sort_field_missing_field() {
         let json = json!({"other_field": 123});
-        extract_sort_field(&json, "/timestamp");
+        let error = extract(&json, "/timestamp").unwrap_err();
+        assert!(error.to_string().contains("Did not find field"));
     }
 
     #[test]
-    #[should_panic(expected = "not of type i64")]
     fn test_extract_sort_field_wrong_type() {
         let json = json!({"timestamp": "not a number"});
-        extract_sort_field(&json, "/timestamp");
+        let error = extract(&json, "/timestamp").unwrap_err();
+        assert!(error.to_string().contains("not of type int"));
+    }
+
+    #[test]
+    fn test_extract_composite_sort_field() {
+        let json = json!({"samplingDate": "2025-07-03", "main": {"offset": 12}});
+        let extractor = extractor(&["/samplingDate:date:desc", "/main/offset:int"], "error");
+        let key = extractor.extract(&json.to_string()).unwrap().unwrap();
+        assert_eq!(key.0.len(), 2);
+        assert_eq!(key.0[1], KeyField::Asc(KeyValue::Int(12)));
+    }
+
+    #[test]
+    fn test_merge_applies_missing_key_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_chunks(
+            dir.path(),
+            // Sorted with missing and null keys first
+            &["{\"o\":null}\n{\"o\":1}\n", "{\"x\":0}\n{\"o\":2}\n"],
+        );
+
+        let merge = |policy: &str| {
+            let extractor = extractor(&["/o"], policy);
+            let mut output = Vec::new();
+            let result = merge_files(files.clone(), &mut output, &extractor);
+            (
+                result,
+                String::from_utf8(output).unwrap(),
+                extractor.counts(),
+            )
+        };
+
+        let (result, output, counts) = merge("drop");
+        result.unwrap();
+        assert_eq!(output, "{\"o\":1}\n{\"o\":2}\n");
+        assert_eq!(counts, (1, 1));
+
+        let (result, output, _) = merge("first");
+        result.unwrap();
+        assert!(output.ends_with("{\"o\":1}\n{\"o\":2}\n"));
+
+        let (result, _, _) = merge("error");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_merge_is_stable_across_rounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunks: Vec<String> = (0..5)
+            .map(|file| {
+                [1, 1, 2]
+                    .iter()
+                    .enumerate()
+                    .map(|(n, o)| format!("{{\"o\":{},\"f\":{},\"n\":{}}}\n", o, file, n))
+                    .collect()
+            })
+            .collect();
+        let files = write_chunks(dir.path(), &chunks);
+
+        let extractor = extractor(&["/o"], "error");
+        let merged =
+            merge_files_in_batches(files.clone(), dir.path(), &extractor, 2, 0, false).unwrap();
+        assert!(files.iter().all(|file| file.exists()));
+        assert_eq!(merged.len(), 3);
+        let mut output = Vec::new();
+        merge_files(merged, &mut output, &extractor).unwrap();
+
+        let order: Vec<(i64, i64)> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let value: Value = serde_json::from_str(line).unwrap();
+                (value["f"].as_i64().unwrap(), value["n"].as_i64().unwrap())
+            })
+            .collect();
+        let mut expected: Vec<(i64, i64)> = (0..5).flat_map(|f| [(f, 0), (f, 1)]).collect();
+        expected.extend((0..5).map(|f| (f, 2)));
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn test_merge_in_batches_removes_consumed_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunks: Vec<String> = (0..3).map(|o| format!("{{\"o\":{}}}\n", o)).collect();
+        let files = write_chunks(dir.path(), &chunks);
+
+        let extractor = extractor(&["/o"], "error");
+        let first =
+            merge_files_in_batches(files.clone(), dir.path(), &extractor, 2, 0, false).unwrap();
+        let second =
+            merge_files_in_batches(first.clone(), dir.path(), &extractor, 2, 1, true).unwrap();
+
+        assert!(files.iter().all(|file| file.exists()));
+        assert!(first.iter().all(|file| !file.exists()));
+        assert_eq!(
+            second,
+            vec![dir.path().join("merged_chunks_1_0.ndjson.zst")]
+        );
+        assert!(second[0].exists());
+    }
+
+    #[test]
+    fn test_merge_writes_lines_verbatim() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunks = ["{\"z\": 1, \"o\": 3}\n", "{ \"o\":2,\"u\":\"\\u00fc\" }\n"];
+        let files = write_chunks(dir.path(), &chunks);
+
+        let mut output = Vec::new();
+        merge_files(files, &mut output, &extractor(&["/o"], "error")).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}{}", chunks[1], chunks[0])
+        );
+    }
+
+    #[test]
+    fn test_final_merge_drops_duplicates_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_chunks(
+            dir.path(),
+            &[
+                "{\"o\":1,\"id\":\"a\",\"f\":0}\n{\"o\":2,\"id\":\"b\",\"f\":0}\n",
+                "{\"o\":1,\"id\":\"a\",\"f\":1}\n{\"o\":3,\"id\":\"c\",\"f\":1}\n",
+            ],
+        );
+
+        let merge = |policy: DedupPolicy| {
+            let dedup = Dedup {
+                pointer: "/id",
+                policy,
+                report: None,
+            };
+            let mut output = Vec::new();
+            let stats = merge_final(
+                files.clone(),
+                &mut output,
+                &extractor(&["/o"], "error").with_extra_pointer("/id"),
+                Some(&dedup),
+            );
+            (stats, String::from_utf8(output).unwrap())
+        };
+
+        let (stats, output) = merge(DedupPolicy::KeepLastBySource);
+        assert_eq!(stats.unwrap().unwrap().removed_records, 1);
+        assert_eq!(
+            output.lines().next(),
+            Some("{\"o\":1,\"id\":\"a\",\"f\":1}")
+        );
+        assert_eq!(output.lines().count(), 3);
+        assert!(merge(DedupPolicy::Error).0.is_err());
+    }
+
+    #[test]
+    fn test_merge_to_file_is_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunk = write_chunks(dir.path(), &["{\"o\":2}\n{\"o\":1}\n"]).remove(0);
+        let output = dir.path().join("sorted.ndjson.zst");
+        let extractor = extractor(&["/o"], "error");
+
+        merge_to_file(vec![chunk.clone()], &output, &extractor, None, 3, 2).unwrap();
+        assert_eq!(read_zstd(&output), "{\"o\":2}\n{\"o\":1}\n");
+        assert!(!output.with_extension("tmp").exists());
+
+        // A failed merge leaves neither a partial nor a temp file behind
+        fs::remove_file(&output).unwrap();
+        write_zstd(&chunk, "{\"x\":1}\n");
+        assert!(merge_to_file(vec![chunk], &output, &extractor, None, 3, 2).is_err());
+        assert!(!output.exists());
+        assert!(!output.with_extension("tmp").exists());
     }
 }
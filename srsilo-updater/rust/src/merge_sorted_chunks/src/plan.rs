@@ -0,0 +1,175 @@
+//! Planning of the merge levels against the free space in the tmp directory.
+//!
+//! Every level rewrites all records, so it takes up about as much space as
+//! the input chunks. A level's files are removed once the next level has
+//! merged them, so at most two levels exist at once (all of them with
+//! `--keep-intermediate`). Fewer levels need less space; if the preferred
+//! fan-in needs too much, a larger one (up to [`MAX_FAN_IN`]) is tried before
+//! giving up.
+
+use srsilo_common::size::format_size;
+
+/// Upper bound for the fan-in: each open file holds a zstd decoder window
+/// (a few MiB at level 3), so this keeps the merge's memory use bounded.
+pub const MAX_FAN_IN: usize = 256;
+
+/// How to merge and how much tmp space it needs.
+#[derive(Debug, PartialEq)]
+pub struct MergePlan {
+    /// Files merged at once
+    pub fan_in: usize,
+    /// Levels of intermediate files written to the tmp directory
+    pub levels: usize,
+    /// Estimated peak use of the tmp directory in bytes
+    pub tmp_space: u64,
+}
+
+/// What the plan is made for.
+pub struct PlanInput {
+    pub chunks: usize,
+    /// Total size of the input chunks in bytes
+    pub input_size: u64,
+    /// `--parallel-files`
+    pub preferred_fan_in: usize,
+    pub keep_intermediate: bool,
+    /// Whether `--output` is on the same file system as the tmp directory
+    pub output_in_tmp_fs: bool,
+    /// Free space in the tmp directory in bytes
+    pub available: u64,
+}
+
+/// Number of intermediate levels with the given fan-in. The first level
+/// always runs; further ones run while more than `fan_in` files are left.
+pub fn levels(chunks: usize, fan_in: usize) -> usize {
+    let mut files = chunks.div_ceil(fan_in);
+    let mut levels = 1;
+    while files > fan_in {
+        files = files.div_ceil(fan_in);
+        levels += 1;
+    }
+    levels
+}
+
+fn tmp_space(input: &PlanInput, levels: usize) -> u64 {
+    let output = usize::from(input.output_in_tmp_fs);
+    let copies = if input.keep_intermediate {
+        levels + output
+    } else {
+        // While writing a level, the previous one still exists; while writing
+        // the output, the last level does
+        levels.min(2).max(1 + output)
+    };
+    input.input_size.saturating_mul(copies as u64)
+}
+
+/// Picks the fan-in: the preferred one if its levels fit into the free space,
+/// otherwise the smallest larger one that fits.
+pub fn plan(input: &PlanInput) -> Result<MergePlan, String> {
+    let candidate = |fan_in: usize| {
+        let levels = levels(input.chunks, fan_in);
+        MergePlan {
+            fan_in,
+            levels,
+            tmp_space: tmp_space(input, levels),
+        }
+    };
+
+    let preferred = candidate(input.preferred_fan_in);
+    if preferred.tmp_space <= input.available {
+        return Ok(preferred);
+    }
+    // Larger fan-ins only help by saving whole levels
+    let mut fan_in = input.preferred_fan_in;
+    while fan_in < MAX_FAN_IN {
+        let current = levels(input.chunks, fan_in);
+        // Smallest fan-in with one level less
+        fan_in = (fan_in + 1..=MAX_FAN_IN)
+            .find(|&f| levels(input.chunks, f) < current)
+            .unwrap_or(MAX_FAN_IN);
+        let larger = candidate(fan_in);
+        if larger.tmp_space <= input.available {
+            return Ok(larger);
+        }
+    }
+
+    let best = candidate(MAX_FAN_IN.max(input.preferred_fan_in));
+    Err(format!(
+        "not enough disk space in the tmp directory to merge {} chunk(s) of {} in total: \
+         {} level(s) need about {}, {} available. Free up space or pass a \
+         --tmp-directory on a larger disk",
+        input.chunks,
+        format_size(input.input_size),
+        best.levels,
+        format_size(best.tmp_space),
+        format_size(input.available)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    fn input(chunks: usize, available: u64) -> PlanInput {
+        PlanInput {
+            chunks,
+            input_size: 10 * GIB,
+            preferred_fan_in: 64,
+            keep_intermediate: false,
+            output_in_tmp_fs: false,
+            available,
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(levels(1, 64), 1);
+        assert_eq!(levels(64 * 64, 64), 1);
+        assert_eq!(levels(64 * 64 + 1, 64), 2);
+        assert_eq!(levels(15, 2), 3);
+    }
+
+    #[test]
+    fn test_preferred_fan_in_when_it_fits() {
+        let plan = plan(&input(10_000, 25 * GIB)).unwrap();
+        assert_eq!(
+            plan,
+            MergePlan {
+                fan_in: 64,
+                levels: 2,
+                tmp_space: 20 * GIB
+            }
+        );
+    }
+
+    #[test]
+    fn test_larger_fan_in_saves_a_level() {
+        // 10000 chunks need two levels at 64, one level from fan-in 100
+        let plan = plan(&input(10_000, 15 * GIB)).unwrap();
+        assert_eq!(plan.fan_in, 100);
+        assert_eq!(plan.levels, 1);
+        assert_eq!(plan.tmp_space, 10 * GIB);
+    }
+
+    #[test]
+    fn test_output_and_kept_levels_count() {
+        let mut same_fs = input(100, 15 * GIB);
+        same_fs.output_in_tmp_fs = true;
+        assert!(plan(&same_fs).is_err());
+        same_fs.available = 20 * GIB;
+        assert_eq!(plan(&same_fs).unwrap().tmp_space, 20 * GIB);
+
+        let mut keep = input(10_000, 25 * GIB);
+        keep.keep_intermediate = true;
+        keep.output_in_tmp_fs = true;
+        assert_eq!(plan(&keep).unwrap().fan_in, 100);
+    }
+
+    #[test]
+    fn test_fails_early_without_space() {
+        let error = plan(&input(10_000, 5 * GIB)).unwrap_err();
+        assert!(error.contains("10000 chunk(s) of 10.0 GiB"));
+        assert!(error.contains("1 level(s) need about 10.0 GiB, 5.0 GiB available"));
+    }
+}
@@ -0,0 +1,25 @@
+//! Helpers for the tests: zstd-compressed NDJSON chunks on disk.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Writes each content as `chunk_<n>.ndjson.zst` into `dir`.
+pub fn write_chunks<S: AsRef<str>>(dir: &Path, contents: &[S]) -> Vec<PathBuf> {
+    contents
+        .iter()
+        .enumerate()
+        .map(|(n, content)| {
+            let path = dir.join(format!("chunk_{}.ndjson.zst", n));
+            write_zstd(&path, content.as_ref());
+            path
+        })
+        .collect()
+}
+
+pub fn write_zstd(path: &Path, content: &str) {
+    fs::write(path, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+}
+
+pub fn read_zstd(path: &Path) -> String {
+    String::from_utf8(zstd::decode_all(File::open(path).unwrap()).unwrap()).unwrap()
+}
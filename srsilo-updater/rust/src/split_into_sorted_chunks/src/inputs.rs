@@ -0,0 +1,160 @@
+//! Resolution of the input arguments to a list of `.ndjson.zst` files.
+//!
+//! Each argument is a file, a directory (all `*.ndjson.zst` files in it) or a
+//! pattern with `*`/`?` wildcards in its file name, e.g. `input/*.ndjson.zst`.
+//! Directory and pattern matches are sorted by name so chunk numbering does
+//! not depend on the file system's listing order.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INPUT_SUFFIX: &str = ".ndjson.zst";
+
+/// Expands the input arguments, keeping their order and dropping duplicates.
+pub fn resolve_inputs(args: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        let matches = if path.is_dir() {
+            matching_files(path, |name| name.ends_with(INPUT_SUFFIX))?
+        } else if has_wildcards(arg) {
+            let (dir, pattern) = split_pattern(path)?;
+            matching_files(&dir, |name| wildcard_match(&pattern, name))?
+        } else if path.is_file() {
+            vec![path.to_path_buf()]
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("input file not found: {}", arg),
+            ));
+        };
+
+        if matches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} files match {}", INPUT_SUFFIX, arg),
+            ));
+        }
+        for input in matches {
+            if !inputs.contains(&input) {
+                inputs.push(input);
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+fn has_wildcards(arg: &str) -> bool {
+    arg.contains(['*', '?'])
+}
+
+/// Splits a pattern into its directory and file name; wildcards are only
+/// supported in the file name.
+fn split_pattern(path: &Path) -> io::Result<(PathBuf, String)> {
+    let pattern = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let dir = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => PathBuf::from("."),
+        Some(parent) => parent.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    if pattern.is_empty() || has_wildcards(&dir.to_string_lossy()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "wildcards are only supported in the file name: {}",
+                path.display()
+            ),
+        ));
+    }
+    Ok((dir, pattern.to_string()))
+}
+
+/// Files in `dir` whose name satisfies `matches`, sorted by name.
+fn matching_files(dir: &Path, matches: impl Fn(&str) -> bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let is_match = entry.file_name().to_str().is_some_and(&matches);
+        if is_match && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Matches `name` against a pattern where `*` is any run of characters and `?` one character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it was matched at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.ndjson.zst", "a.ndjson.zst"));
+        assert!(wildcard_match("*.ndjson.zst", ".ndjson.zst"));
+        assert!(wildcard_match("part_?.ndjson.*", "part_1.ndjson.zst"));
+        assert!(wildcard_match("*a*b*", "xxaybb"));
+        assert!(!wildcard_match("*.ndjson.zst", "a.ndjson"));
+        assert!(!wildcard_match("part_?.zst", "part_10.zst"));
+        assert!(!wildcard_match("a", "ab"));
+    }
+
+    #[test]
+    fn test_resolve_inputs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for name in ["b.ndjson.zst", "a.ndjson.zst", "notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let dir_arg = dir.to_str().unwrap().to_string();
+        let file_b = dir.join("b.ndjson.zst");
+
+        // Directories are sorted and skip other files; duplicates are dropped
+        let inputs =
+            resolve_inputs(&[file_b.to_str().unwrap().to_string(), dir_arg.clone()]).unwrap();
+        assert_eq!(inputs, vec![file_b.clone(), dir.join("a.ndjson.zst")]);
+
+        let pattern = format!("{}/?.ndjson.zst", dir_arg);
+        assert_eq!(
+            resolve_inputs(&[pattern]).unwrap(),
+            vec![dir.join("a.ndjson.zst"), file_b]
+        );
+
+        assert!(resolve_inputs(&[format!("{}/*.tsv", dir_arg)]).is_err());
+        assert!(resolve_inputs(&[format!("{}/missing.ndjson.zst", dir_arg)]).is_err());
+    }
+}
@@ -1,54 +1,219 @@
 use clap::Parser;
-use serde_json::Value;
+use rayon::slice::ParallelSliceMut;
+use srsilo_common::lock;
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use srsilo_common::size::{format_size, parse_size};
+use srsilo_common::sort_key::{self, KeyExtractor, MissingKeyPolicy, SortKey, SortKeySpec};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::File;
-use std::io::{stdin, BufRead, BufReader, BufWriter, Write};
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
-use zstd::stream::Encoder;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use zstd::stream::{Decoder, Encoder};
 
-fn write_ndjson_lines<W: Write>(writer: &mut W, lines: &[Value]) -> std::io::Result<()> {
+mod inputs;
+
+/// An input line, kept byte for byte, with its sort key.
+struct Record {
+    sort_key: SortKey,
+    line: String,
+}
+
+impl Record {
+    /// Heap and inline bytes this record occupies while buffered.
+    fn memory_size(&self) -> u64 {
+        (mem::size_of::<Record>() + self.line.capacity() + self.sort_key.heap_size()) as u64
+    }
+}
+
+fn write_ndjson_lines<W: Write>(writer: &mut W, records: &[Record]) -> std::io::Result<()> {
     let mut writer = BufWriter::new(writer);
-    for line in lines {
-        writeln!(writer, "{}", line)?;
+    for record in records {
+        writeln!(writer, "{}", record.line)?;
     }
     Ok(())
 }
 
-fn sort_by(mut lines: Vec<Value>, sort_field_path: &String) -> Vec<Value> {
-    lines.sort_by(|a, b| {
-        let a_length = a
-            .pointer(sort_field_path)
-            .unwrap_or_else(|| panic!("Did not find field {sort_field_path} in object {a}"))
-            .as_i64()
-            .unwrap_or(0);
-        let b_length = b
-            .pointer(sort_field_path)
-            .unwrap_or_else(|| panic!("Did not find field {sort_field_path} in object {b}"))
-            .as_i64()
-            .unwrap_or(0);
-        a_length.cmp(&b_length)
-    });
-    lines
+/// Reads the sort key from a line without parsing the rest of the record.
+///
+/// Returns `None` if the missing key policy drops the record.
+fn read_record(line: String, extractor: &KeyExtractor) -> std::io::Result<Option<Record>> {
+    Ok(extractor
+        .extract(&line)?
+        .map(|sort_key| Record { sort_key, line }))
+}
+
+fn sort_by(mut records: Vec<Record>) -> Vec<Record> {
+    records.par_sort_by(|a, b| a.sort_key.cmp(&b.sort_key));
+    records
+}
+
+/// Where records are read from.
+enum Source {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Source {
+    fn open(&self) -> std::io::Result<Box<dyn BufRead>> {
+        Ok(match self {
+            Source::Stdin => Box::new(stdin().lock()),
+            Source::File(path) => Box::new(BufReader::new(Decoder::new(File::open(path)?)?)),
+        })
+    }
+}
+
+/// A full chunk waiting to be sorted and written.
+struct Chunk {
+    /// Index of the source the records were read from
+    source: usize,
+    number: usize,
+    records: Vec<Record>,
+}
+
+/// Progress reported to the thread printing the chunk list.
+enum Progress {
+    Written {
+        source: usize,
+        number: usize,
+        result: std::io::Result<PathBuf>,
+    },
+    /// A source was read completely into `chunks` chunks
+    SourceDone { source: usize, chunks: usize },
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Input .ndjson.zst files, directories or file name patterns (reads stdin if none)
+    inputs: Vec<String>,
+
     #[arg(long)]
     output_path: String,
 
     #[arg(long, default_value = "chunk")]
     filename_stem: String,
 
+    /// Sort key as `<json pointer>[:int|float|string|date[:asc|desc]]`; repeat or
+    /// separate with commas to break ties, e.g. `/main/offset:int,/readId:string`
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "sort_field_path",
+        conflicts_with = "sort_field_path"
+    )]
+    sort_key: Vec<SortKeySpec>,
+
+    /// Single ascending integer sort field (same as `--sort-key <path>:int`)
+    #[arg(long)]
+    sort_field_path: Option<String>,
+
+    /// What to do with records whose sort key is missing or null:
+    /// error, first, last, drop or default=<value> (use the same in merge_sorted_chunks)
+    #[arg(long, default_value = "error")]
+    missing_key: MissingKeyPolicy,
+
+    /// Maximum number of lines per chunk (10000 if no byte limit is given)
+    #[arg(long)]
+    chunk_size: Option<usize>,
+
+    /// Flush a chunk once its buffered records use this much memory (e.g. 512M)
+    #[arg(long, value_parser = parse_size, conflicts_with = "memory_limit")]
+    max_chunk_bytes: Option<u64>,
+
+    /// Memory budget for all buffered records (e.g. 4G)
+    #[arg(long, value_parser = parse_size)]
+    memory_limit: Option<u64>,
+
+    /// Number of full chunks sorted and compressed concurrently while reading continues
+    #[arg(long, default_value_t = 4)]
+    in_flight_chunks: usize,
+
+    /// Number of input files decoded concurrently
+    #[arg(long, default_value_t = 4)]
+    decode_threads: usize,
+
+    /// Also write the chunk paths, one per line in chunk order, to this file
     #[arg(long)]
-    sort_field_path: String,
+    chunk_list: Option<PathBuf>,
 
-    #[arg(long, default_value_t = 10000)]
-    chunk_size: usize,
+    /// Organism base directory to lock against concurrent runs (no locking if omitted)
+    #[arg(long)]
+    lock_dir: Option<String>,
+
+    /// URL to POST an error event to as JSON if the run fails
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
+}
+
+const DEFAULT_CHUNK_SIZE: usize = 10000;
+
+/// When to flush the chunk being read.
+#[derive(Debug, PartialEq)]
+struct ChunkLimits {
+    max_lines: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+impl ChunkLimits {
+    /// `readers` is the number of chunks being filled concurrently.
+    fn new(args: &Args, readers: usize) -> Self {
+        // The chunks being read are buffered alongside the in-flight ones
+        let buffered_chunks = (args.in_flight_chunks + readers) as u64;
+        let max_bytes = args
+            .max_chunk_bytes
+            .or(args.memory_limit.map(|limit| limit / buffered_chunks));
+        let max_lines = match (args.chunk_size, max_bytes) {
+            (Some(lines), _) => Some(lines),
+            (None, Some(_)) => None,
+            (None, None) => Some(DEFAULT_CHUNK_SIZE),
+        };
+        ChunkLimits {
+            max_lines,
+            max_bytes,
+        }
+    }
+
+    fn is_full(&self, lines: usize, bytes: u64) -> bool {
+        self.max_lines.is_some_and(|max| lines >= max)
+            || self.max_bytes.is_some_and(|max| bytes >= max)
+    }
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    let notifier = Notifier::new(args.webhook_url.as_deref(), "split_into_sorted_chunks");
+    notifier.report_panics();
+
+    let result = run(&args, &mut stdout().lock());
+    if let Err(e) = &result {
+        notifier.error(&e.to_string());
+    }
+    result
+}
+
+/// Splits the inputs into sorted chunks, printing their paths to `stdout`.
+fn run(args: &Args, stdout: &mut impl Write) -> std::io::Result<()> {
+    let _lock = match &args.lock_dir {
+        Some(dir) => Some(lock::acquire_or_exit(
+            Path::new(dir),
+            "split_into_sorted_chunks",
+        )?),
+        None => None,
+    };
+
+    let sources: Vec<Source> = if args.inputs.is_empty() {
+        vec![Source::Stdin]
+    } else {
+        inputs::resolve_inputs(&args.inputs)?
+            .into_iter()
+            .map(Source::File)
+            .collect()
+    };
 
     let output_path = Path::new(&args.output_path);
 
@@ -62,45 +227,458 @@ fn main() -> std::io::Result<()> {
         fs::create_dir_all(output_path)?
     };
 
+    if args.in_flight_chunks == 0 || args.decode_threads == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--in-flight-chunks and --decode-threads must be greater than zero",
+        ));
+    }
+    let readers = args.decode_threads.min(sources.len());
+    let sort_keys = sort_key::resolve_sort_keys(&args.sort_key, args.sort_field_path.as_deref());
+    let extractor = KeyExtractor::new(sort_keys, args.missing_key.clone())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let limits = ChunkLimits::new(args, readers);
+    if limits.max_lines == Some(0) || limits.max_bytes == Some(0) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "chunk limits must be greater than zero",
+        ));
+    }
+    if let Some(max_bytes) = limits.max_bytes {
+        eprintln!(
+            "Flushing chunks at {} of buffered records",
+            format_size(max_bytes)
+        );
+    }
+
+    // Created up front, as a failure once the readers run would leave them blocked
+    let list_writer = match &args.chunk_list {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+
+    // Readers hand full chunks to the writers one at a time, so at most
+    // `in_flight_chunks` are being written while the next ones are read.
+    // A failing writer (or chunk list) drops the receiver, so readers blocked in
+    // `send` return.
+    let (chunk_sender, chunk_receiver) = mpsc::sync_channel::<Chunk>(0);
+    let chunk_receiver = Mutex::new(Some(chunk_receiver));
+    let (progress_sender, progress_receiver) = mpsc::channel();
+    let several_sources = sources.len() > 1;
+    let next_source = AtomicUsize::new(0);
+    let aborted = AtomicBool::new(false);
+
+    let result = thread::scope(|scope| {
+        for _ in 0..args.in_flight_chunks {
+            let (chunk_receiver, aborted) = (&chunk_receiver, &aborted);
+            let progress_sender = progress_sender.clone();
+            scope.spawn(move || loop {
+                let received = match chunk_receiver.lock().unwrap().as_ref() {
+                    Some(receiver) => receiver.recv(),
+                    None => break,
+                };
+                let Ok(chunk) = received else {
+                    break;
+                };
+                let (source, number) = (chunk.source, chunk.number);
+                let result = write_chunk(args, output_path, chunk, several_sources);
+                let failed = result.is_err();
+                let progress = Progress::Written {
+                    source,
+                    number,
+                    result,
+                };
+                if failed {
+                    aborted.store(true, Ordering::Relaxed);
+                    chunk_receiver.lock().unwrap().take();
+                }
+                if progress_sender.send(progress).is_err() || failed {
+                    break;
+                }
+            });
+        }
+
+        let reader_threads: Vec<_> = (0..readers)
+            .map(|_| {
+                let chunk_sender = chunk_sender.clone();
+                let progress_sender = progress_sender.clone();
+                let (sources, extractor, limits) = (&sources, &extractor, &limits);
+                let (next_source, aborted) = (&next_source, &aborted);
+                scope.spawn(move || -> std::io::Result<()> {
+                    while !aborted.load(Ordering::Relaxed) {
+                        let index = next_source.fetch_add(1, Ordering::Relaxed);
+                        let Some(source) = sources.get(index) else {
+                            break;
+                        };
+                        match read_chunks(extractor, limits, index, source, &chunk_sender, aborted)
+                        {
+                            Ok(Some(chunks)) => {
+                                let _ = progress_sender.send(Progress::SourceDone {
+                                    source: index,
+                                    chunks,
+                                });
+                            }
+                            // All writers stopped on an error, which the printer reports
+                            Ok(None) => break,
+                            Err(e) => {
+                                aborted.store(true, Ordering::Relaxed);
+                                return Err(e);
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        drop(chunk_sender);
+        drop(progress_sender);
+
+        let write_result = print_in_order(progress_receiver, stdout, list_writer);
+        if write_result.is_err() {
+            aborted.store(true, Ordering::Relaxed);
+            chunk_receiver.lock().unwrap().take();
+        }
+        let read_result = reader_threads
+            .into_iter()
+            .map(|reader| reader.join().unwrap())
+            .collect::<std::io::Result<Vec<()>>>();
+        read_result.and(write_result)
+    });
+
+    if let Some(summary) = extractor.summary() {
+        eprintln!("{}", summary);
+    }
+    result
+}
+
+/// Reads one source into chunks and sends each full chunk to the writers.
+///
+/// Returns the number of chunks, or `None` if the writers stopped.
+fn read_chunks(
+    extractor: &KeyExtractor,
+    limits: &ChunkLimits,
+    source_index: usize,
+    source: &Source,
+    chunk_sender: &mpsc::SyncSender<Chunk>,
+    aborted: &AtomicBool,
+) -> std::io::Result<Option<usize>> {
     let mut chunk_counter = 0;
 
-    let reader = stdin();
-    let reader = reader.lock();
+    let reader = source.open()?;
     let mut lines = Vec::new();
+    let mut buffered_bytes = 0;
 
-    for line in BufReader::new(reader).lines() {
-        let line = line?;
-        let json: Value = serde_json::from_str(&line)?;
-        lines.push(json);
-
-        if lines.len() >= args.chunk_size {
-            let sorted_lines = sort_by(lines, &args.sort_field_path);
-            let chunk_file: PathBuf = Path::join(
-                output_path,
-                format!("{}_{}.ndjson.zst", args.filename_stem, chunk_counter),
-            );
-            let file = File::create(chunk_file.clone())?;
-            let mut encoder = Encoder::new(file, 3)?;
-            write_ndjson_lines(&mut encoder, &sorted_lines)?;
-            encoder.finish()?;
-            println!("{}", chunk_file.as_path().to_str().unwrap());
-            lines = Vec::new();
+    for line in reader.lines() {
+        let Some(record) = read_record(line?, extractor)? else {
+            continue;
+        };
+        buffered_bytes += record.memory_size();
+        lines.push(record);
+
+        if limits.is_full(lines.len(), buffered_bytes) {
+            if aborted.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let chunk = Chunk {
+                source: source_index,
+                number: chunk_counter,
+                records: mem::take(&mut lines),
+            };
+            if chunk_sender.send(chunk).is_err() {
+                return Ok(None);
+            }
+            buffered_bytes = 0;
             chunk_counter += 1;
         }
     }
 
     // Process any remaining lines
     if !lines.is_empty() {
-        let sorted_lines = sort_by(lines, &args.sort_field_path);
-        let chunk_file: PathBuf = Path::join(
-            output_path,
-            format!("{}_{}.ndjson.zst", args.filename_stem, chunk_counter),
+        let chunk = Chunk {
+            source: source_index,
+            number: chunk_counter,
+            records: lines,
+        };
+        if chunk_sender.send(chunk).is_err() {
+            return Ok(None);
+        }
+        chunk_counter += 1;
+    }
+    Ok(Some(chunk_counter))
+}
+
+/// Prints (and optionally lists) the written chunk paths in source and chunk
+/// order, returning the first write error.
+fn print_in_order(
+    progress_receiver: mpsc::Receiver<Progress>,
+    stdout: &mut impl Write,
+    mut list_writer: Option<BufWriter<File>>,
+) -> std::io::Result<()> {
+    let mut pending = BTreeMap::new();
+    let mut source_chunks = HashMap::new();
+    let (mut next_source, mut next_number) = (0, 0);
+    let mut first_error = None;
+
+    for progress in progress_receiver {
+        match progress {
+            Progress::Written {
+                source,
+                number,
+                result: Ok(path),
+            } => {
+                pending.insert((source, number), path);
+            }
+            Progress::Written { result: Err(e), .. } => {
+                first_error.get_or_insert(e);
+            }
+            Progress::SourceDone { source, chunks } => {
+                source_chunks.insert(source, chunks);
+            }
+        }
+
+        loop {
+            if let Some(path) = pending.remove(&(next_source, next_number)) {
+                let path = path.to_str().unwrap();
+                writeln!(stdout, "{}", path)?;
+                if let Some(writer) = list_writer.as_mut() {
+                    writeln!(writer, "{}", path)?;
+                }
+                next_number += 1;
+            } else if source_chunks.get(&next_source) == Some(&next_number) {
+                next_source += 1;
+                next_number = 0;
+            } else {
+                break;
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => {
+            if let Some(mut writer) = list_writer {
+                writer.flush()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Sorts the chunk's records and writes them to the output directory.
+///
+/// With several sources, the file name includes the source index.
+fn write_chunk(
+    args: &Args,
+    output_path: &Path,
+    chunk: Chunk,
+    several_sources: bool,
+) -> std::io::Result<PathBuf> {
+    let file_name = if several_sources {
+        format!(
+            "{}_{}_{}.ndjson.zst",
+            args.filename_stem, chunk.source, chunk.number
+        )
+    } else {
+        format!("{}_{}.ndjson.zst", args.filename_stem, chunk.number)
+    };
+    let sorted_lines = sort_by(chunk.records);
+    let chunk_file: PathBuf = Path::join(output_path, file_name);
+    let file = File::create(chunk_file.clone())?;
+    let mut encoder = Encoder::new(file, 3)?;
+    write_ndjson_lines(&mut encoder, &sorted_lines)?;
+    encoder.finish()?;
+    Ok(chunk_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(extra: &[&str]) -> Result<Args, clap::Error> {
+        let base = ["split_into_sorted_chunks", "--sort-key", "/o"];
+        Args::try_parse_from(base.iter().chain(extra))
+    }
+
+    fn chunk_limits(extra: &[&str], readers: usize) -> ChunkLimits {
+        let args = parse_args(&[&["--output-path", "out"], extra].concat()).unwrap();
+        ChunkLimits::new(&args, readers)
+    }
+
+    #[test]
+    fn test_default_chunk_limits() {
+        let limits = chunk_limits(&[], 1);
+        assert_eq!(
+            limits,
+            ChunkLimits {
+                max_lines: Some(DEFAULT_CHUNK_SIZE),
+                max_bytes: None
+            }
         );
-        let file = File::create(chunk_file.clone())?;
-        let mut encoder = Encoder::new(file, 3)?;
-        write_ndjson_lines(&mut encoder, &sorted_lines)?;
-        encoder.finish()?;
-        println!("{}", chunk_file.as_path().to_str().unwrap());
+        assert!(!limits.is_full(DEFAULT_CHUNK_SIZE - 1, u64::MAX));
+        assert!(limits.is_full(DEFAULT_CHUNK_SIZE, 0));
+    }
+
+    #[test]
+    fn test_max_chunk_bytes_without_line_limit() {
+        let limits = chunk_limits(&["--max-chunk-bytes", "1K"], 2);
+        assert_eq!(
+            limits,
+            ChunkLimits {
+                max_lines: None,
+                max_bytes: Some(1024)
+            }
+        );
+        assert!(!limits.is_full(usize::MAX, 1023));
+        assert!(limits.is_full(1, 1024));
+
+        // An explicit line limit applies as well
+        let limits = chunk_limits(&["--max-chunk-bytes", "1K", "--chunk-size", "5"], 2);
+        assert!(limits.is_full(5, 0));
+        assert!(limits.is_full(1, 1024));
+    }
+
+    #[test]
+    fn test_memory_limit_is_shared_by_buffered_chunks() {
+        // 4 in-flight chunks and 2 chunks being read share the budget
+        let limits = chunk_limits(&["--memory-limit", "6K", "--in-flight-chunks", "4"], 2);
+        assert_eq!(
+            limits,
+            ChunkLimits {
+                max_lines: None,
+                max_bytes: Some(1024)
+            }
+        );
+        let limits = chunk_limits(&["--memory-limit", "6K", "--in-flight-chunks", "1"], 1);
+        assert_eq!(limits.max_bytes, Some(3 * 1024));
+    }
+
+    #[test]
+    fn test_max_chunk_bytes_conflicts_with_memory_limit() {
+        let error = parse_args(&[
+            "--output-path",
+            "out",
+            "--max-chunk-bytes",
+            "1K",
+            "--memory-limit",
+            "6K",
+        ])
+        .unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_chunks_are_listed_in_source_order() {
+        let dir = tempfile::tempdir().unwrap();
+        // Sources of different lengths, so their chunks finish out of order
+        let lengths: [usize; 3] = [50, 5, 23];
+        let inputs: Vec<String> = lengths
+            .iter()
+            .enumerate()
+            .map(|(source, &length)| {
+                let input = dir.path().join(format!("input_{}.ndjson.zst", source));
+                let content: String = (0..length)
+                    .rev()
+                    .map(|o| format!("{{\"o\":{}}}\n", o))
+                    .collect();
+                fs::write(&input, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+                input.to_str().unwrap().to_string()
+            })
+            .collect();
+
+        let output = dir.path().join("chunks");
+        let chunk_list = dir.path().join("chunks.txt");
+        let mut arguments = vec![
+            "--chunk-size",
+            "4",
+            "--in-flight-chunks",
+            "4",
+            "--decode-threads",
+            "3",
+            "--output-path",
+            output.to_str().unwrap(),
+            "--chunk-list",
+            chunk_list.to_str().unwrap(),
+        ];
+        arguments.extend(inputs.iter().map(String::as_str));
+        let mut stdout = Vec::new();
+        run(&parse_args(&arguments).unwrap(), &mut stdout).unwrap();
+
+        let expected: String = lengths
+            .iter()
+            .enumerate()
+            .flat_map(|(source, &length)| {
+                let output = &output;
+                (0..length.div_ceil(4)).map(move |number| {
+                    let path = output.join(format!("chunk_{}_{}.ndjson.zst", source, number));
+                    format!("{}\n", path.display())
+                })
+            })
+            .collect();
+        assert_eq!(String::from_utf8(stdout).unwrap(), expected);
+        assert_eq!(fs::read_to_string(&chunk_list).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_failing_chunk_write_stops_the_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.ndjson.zst");
+        let content: String = (0..100).map(|o| format!("{{\"o\":{}}}\n", o)).collect();
+        fs::write(&input, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+
+        // Chunk files go into a directory that does not exist, which fails even as root
+        let output = dir.path().join("chunks");
+        let args = Args::try_parse_from([
+            "split_into_sorted_chunks",
+            input.to_str().unwrap(),
+            "--output-path",
+            output.to_str().unwrap(),
+            "--filename-stem",
+            "missing/chunk",
+            "--sort-key",
+            "/o",
+            "--chunk-size",
+            "1",
+            "--in-flight-chunks",
+            "2",
+        ])
+        .unwrap();
+
+        let error = run(&args, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_failing_chunk_list_stops_the_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.ndjson.zst");
+        let content: String = (0..2000).map(|o| format!("{{\"o\":{}}}\n", o)).collect();
+        fs::write(&input, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+
+        let split = |output: &str, chunk_list: &Path| {
+            let output = dir.path().join(output);
+            let args = Args::try_parse_from([
+                "split_into_sorted_chunks",
+                input.to_str().unwrap(),
+                "--output-path",
+                output.to_str().unwrap(),
+                "--sort-key",
+                "/o",
+                "--chunk-size",
+                "1",
+                "--in-flight-chunks",
+                "1",
+                "--chunk-list",
+                chunk_list.to_str().unwrap(),
+            ])
+            .unwrap();
+            run(&args, &mut Vec::new()).unwrap_err()
+        };
+
+        // The list cannot be created
+        let error = split("chunks_0", &dir.path().join("missing/list"));
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        // Writing the list fails once its buffer is flushed mid-run
+        let error = split("chunks_1", Path::new("/dev/full"));
+        assert_eq!(error.kind(), std::io::ErrorKind::StorageFull);
     }
-    Ok(())
 }
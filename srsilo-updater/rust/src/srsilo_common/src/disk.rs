@@ -0,0 +1,62 @@
+//! Free disk space checks, so a run fails before hours of work instead of
+//! when the disk fills up.
+
+use crate::size::format_size;
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Bytes available to unprivileged users on the file system holding `path`.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stat` is only read after statvfs succeeded
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Fails with `StorageFull` if `path` has less than `needed` bytes available.
+/// `what` names the data in the error, e.g. "intermediate merge files".
+pub fn ensure_available(path: &Path, needed: u64, what: &str) -> io::Result<()> {
+    let available = available_space(path)?;
+    if available < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "not enough disk space in {} for {}: need {}, {} available",
+                path.display(),
+                what,
+                format_size(needed),
+                format_size(available)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available_space() {
+        let available = available_space(&std::env::temp_dir()).unwrap();
+        assert!(available > 0);
+        assert!(ensure_available(&std::env::temp_dir(), 1, "a test").is_ok());
+
+        let error = ensure_available(&std::env::temp_dir(), u64::MAX, "a test").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert!(error.to_string().contains("for a test: need"));
+
+        assert!(available_space(Path::new("/does/not/exist")).is_err());
+    }
+}
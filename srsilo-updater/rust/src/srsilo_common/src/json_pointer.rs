@@ -0,0 +1,306 @@
+//! JSON pointer lookup on raw NDJSON lines.
+//!
+//! The sort tools only need a few fields of multi-kilobyte records.
+//! [`PointerSet::extract`] walks the line once for all of its pointers,
+//! skipping every other value without building it, and returns the matching
+//! values as slices of the original line.
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use std::fmt;
+
+/// JSON pointers (RFC 6901), parsed once and looked up together.
+#[derive(Debug, Clone)]
+pub struct PointerSet {
+    /// Unescaped reference tokens per pointer; `None` if the pointer cannot resolve
+    paths: Vec<Option<Vec<String>>>,
+}
+
+impl PointerSet {
+    pub fn new<S: AsRef<str>>(pointers: impl IntoIterator<Item = S>) -> Self {
+        let paths = pointers
+            .into_iter()
+            .map(|pointer| {
+                let pointer = pointer.as_ref();
+                if pointer.is_empty() {
+                    return Some(Vec::new());
+                }
+                let path = pointer.strip_prefix('/')?;
+                Some(
+                    path.split('/')
+                        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+                        .collect(),
+                )
+            })
+            .collect();
+        PointerSet { paths }
+    }
+
+    /// Returns the raw JSON value at each pointer in `line`, in the order the
+    /// pointers were given, or `None` where a pointer does not resolve.
+    ///
+    /// Errors if `line` is not valid JSON.
+    pub fn extract<'a>(&self, line: &'a str) -> serde_json::Result<Vec<Option<&'a RawValue>>> {
+        let mut values = vec![None; self.paths.len()];
+        let mut whole = Vec::new();
+        let mut nested = Vec::new();
+        for (index, path) in self.paths.iter().enumerate() {
+            match path.as_deref() {
+                Some([]) => whole.push(index),
+                Some(tokens) => nested.push((index, tokens)),
+                None => {}
+            }
+        }
+
+        if whole.is_empty() {
+            let mut deserializer = serde_json::Deserializer::from_str(line);
+            PathsSeed {
+                paths: nested,
+                values: &mut values,
+            }
+            .deserialize(&mut deserializer)?;
+            deserializer.end()?;
+        } else {
+            // Only the empty pointer needs the whole line as one value
+            let root: &'a RawValue = serde_json::from_str(line)?;
+            capture(root, &whole, nested, &mut values)?;
+        }
+        Ok(values)
+    }
+}
+
+/// Returns the raw JSON value at `pointer` (RFC 6901) in `line`, or `None` if
+/// the pointer does not resolve.
+///
+/// Errors if `line` is not valid JSON.
+pub fn extract_raw<'a>(line: &'a str, pointer: &str) -> serde_json::Result<Option<&'a RawValue>> {
+    Ok(PointerSet::new([pointer]).extract(line)?.pop().flatten())
+}
+
+/// Stores `value` for the pointers ending at it and looks up the ones below it.
+fn capture<'de>(
+    value: &'de RawValue,
+    ending: &[usize],
+    below: Vec<(usize, &[String])>,
+    values: &mut [Option<&'de RawValue>],
+) -> serde_json::Result<()> {
+    for &index in ending {
+        values[index] = Some(value);
+    }
+    if !below.is_empty() {
+        let mut deserializer = serde_json::Deserializer::from_str(value.get());
+        PathsSeed {
+            paths: below,
+            values,
+        }
+        .deserialize(&mut deserializer)?;
+    }
+    Ok(())
+}
+
+/// Looks up the remaining tokens of several pointers below one value,
+/// skipping all children no pointer leads into.
+struct PathsSeed<'p, 'v, 'de> {
+    /// (index of the pointer, its remaining tokens), never empty
+    paths: Vec<(usize, &'p [String])>,
+    values: &'v mut [Option<&'de RawValue>],
+}
+
+impl<'p> PathsSeed<'p, '_, '_> {
+    /// Splits the pointers continuing at `child` into those ending there and
+    /// those leading further down.
+    fn continuing(&self, child: impl Fn(&str) -> bool) -> (Vec<usize>, Vec<(usize, &'p [String])>) {
+        let mut ending = Vec::new();
+        let mut below = Vec::new();
+        for &(index, tokens) in &self.paths {
+            if child(&tokens[0]) {
+                match &tokens[1..] {
+                    [] => ending.push(index),
+                    rest => below.push((index, rest)),
+                }
+            }
+        }
+        (ending, below)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for PathsSeed<'_, '_, 'de> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PathsSeed<'_, '_, 'de> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            let (ending, below) = self.continuing(|token| token == key);
+            if !ending.is_empty() {
+                let value = map.next_value::<&'de RawValue>()?;
+                capture(value, &ending, below, self.values).map_err(de::Error::custom)?;
+            } else if !below.is_empty() {
+                map.next_value_seed(PathsSeed {
+                    paths: below,
+                    values: &mut *self.values,
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut position = 0;
+        loop {
+            let (ending, below) =
+                self.continuing(|token| token.parse::<usize>().ok() == Some(position));
+            let more = if !ending.is_empty() {
+                match seq.next_element::<&'de RawValue>()? {
+                    Some(value) => {
+                        capture(value, &ending, below, self.values).map_err(de::Error::custom)?;
+                        true
+                    }
+                    None => false,
+                }
+            } else if !below.is_empty() {
+                seq.next_element_seed(PathsSeed {
+                    paths: below,
+                    values: &mut *self.values,
+                })?
+                .is_some()
+            } else {
+                seq.next_element::<IgnoredAny>()?.is_some()
+            };
+            if !more {
+                return Ok(());
+            }
+            position += 1;
+        }
+    }
+
+    // Scalars have no children
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<Self::Value, E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<Self::Value, E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<Self::Value, E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<Self::Value, E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str =
+        r#"{"readId":"r1","main":{"offset":42,"sequence":"ACGT"},"a/b":{"~x":[1,{"y":null}]}}"#;
+
+    fn raw(pointer: &str) -> Option<String> {
+        extract_raw(LINE, pointer)
+            .unwrap()
+            .map(|v| v.get().to_string())
+    }
+
+    #[test]
+    fn test_extract_nested_field() {
+        assert_eq!(raw("/main/offset").as_deref(), Some("42"));
+        assert_eq!(raw("/readId").as_deref(), Some(r#""r1""#));
+        assert_eq!(
+            raw("/main").as_deref(),
+            Some(r#"{"offset":42,"sequence":"ACGT"}"#)
+        );
+    }
+
+    #[test]
+    fn test_extract_escaped_tokens_and_arrays() {
+        assert_eq!(raw("/a~1b/~0x/0").as_deref(), Some("1"));
+        assert_eq!(raw("/a~1b/~0x/1/y").as_deref(), Some("null"));
+        assert_eq!(raw("/a~1b/~0x/2"), None);
+    }
+
+    #[test]
+    fn test_missing_paths() {
+        assert_eq!(raw("/main/length"), None);
+        assert_eq!(raw("/readId/0"), None);
+        assert_eq!(raw("main"), None);
+    }
+
+    #[test]
+    fn test_matches_value_pointer() {
+        let value: serde_json::Value = serde_json::from_str(LINE).unwrap();
+        for pointer in ["/main/offset", "/a~1b/~0x/1", "/missing", ""] {
+            let expected = value.pointer(pointer).cloned();
+            let actual = extract_raw(LINE, pointer)
+                .unwrap()
+                .map(|v| serde_json::from_str::<serde_json::Value>(v.get()).unwrap());
+            assert_eq!(actual, expected, "pointer {}", pointer);
+        }
+    }
+
+    #[test]
+    fn test_raw_value_excludes_whitespace() {
+        let line = r#"{ "main" : { "offset" : 42 } }"#;
+        let value = extract_raw(line, "/main/offset").unwrap().unwrap();
+        assert_eq!(value.get(), "42");
+    }
+
+    #[test]
+    fn test_pointer_set_matches_value_pointer() {
+        let pointers = [
+            "/main/offset",
+            "/main",
+            "/readId",
+            "/a~1b/~0x/1/y",
+            "/a~1b/~0x/0",
+            "/missing",
+            "main",
+            "",
+        ];
+        let value: serde_json::Value = serde_json::from_str(LINE).unwrap();
+        let values = PointerSet::new(pointers).extract(LINE).unwrap();
+        assert_eq!(values.len(), pointers.len());
+        for (pointer, actual) in pointers.iter().zip(values) {
+            let actual =
+                actual.map(|v| serde_json::from_str::<serde_json::Value>(v.get()).unwrap());
+            assert_eq!(
+                actual,
+                value.pointer(pointer).cloned(),
+                "pointer {}",
+                pointer
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_is_an_error() {
+        assert!(extract_raw("{\"main\":", "/main").is_err());
+        assert!(extract_raw("", "/main").is_err());
+        assert!(extract_raw("{\"main\":1} x", "/main").is_err());
+        let set = PointerSet::new(["/a", "/b"]);
+        assert!(set.extract("{\"a\":1,\"b\":[1,}").is_err());
+    }
+}
@@ -0,0 +1,9 @@
+//! Shared building blocks for the srSILO updater binaries.
+
+pub mod disk;
+pub mod json_pointer;
+pub mod lock;
+pub mod notify;
+pub mod size;
+pub mod sort_key;
+pub mod state;
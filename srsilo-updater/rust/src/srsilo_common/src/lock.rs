@@ -0,0 +1,308 @@
+//! Advisory lock preventing concurrent pipeline runs per organism.
+//!
+//! The lock is a file in the organism's base directory recording the owner's
+//! PID and hostname. It is created atomically (hard link of a fully written
+//! temp file) and removed when the guard is dropped. A lock left behind by a
+//! process that no longer exists on this host is considered stale and taken
+//! over; locks from other hosts are never taken over.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Name of the lock file inside the locked directory.
+pub const LOCK_FILE_NAME: &str = ".srsilo.lock";
+
+/// Exit code used by all tools when another run holds the lock.
+pub const LOCK_HELD_EXIT_CODE: i32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub hostname: String,
+    pub tool: String,
+    pub acquired_at: i64,
+}
+
+impl fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (pid {} on {}, since {})",
+            self.tool, self.pid, self.hostname, self.acquired_at
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    Held { path: PathBuf, owner: LockOwner },
+    Io(io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Held { path, owner } => write!(
+                f,
+                "Another run holds the lock {}: {}",
+                path.display(),
+                owner
+            ),
+            LockError::Io(e) => write!(f, "Failed to acquire lock: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+/// Held lock; the lock file is removed on drop.
+#[derive(Debug)]
+pub struct LockGuard {
+    path: PathBuf,
+}
+
+impl LockGuard {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Acquires the lock in `dir` on behalf of `tool`.
+pub fn acquire(dir: &Path, tool: &str) -> Result<LockGuard, LockError> {
+    let path = dir.join(LOCK_FILE_NAME);
+    let owner = LockOwner {
+        pid: std::process::id(),
+        hostname: hostname(),
+        tool: tool.to_string(),
+        acquired_at: Utc::now().timestamp(),
+    };
+
+    // One retry after removing a stale lock
+    for _ in 0..2 {
+        if try_create(&path, &owner)? {
+            return Ok(LockGuard { path });
+        }
+
+        match read_owner(&path)? {
+            // Released between our attempt and the read
+            None => continue,
+            Some(existing) if is_stale(&existing, &owner.hostname) => {
+                eprintln!(
+                    "WARNING: Removing stale lock {} held by {}",
+                    path.display(),
+                    existing
+                );
+                remove_if_exists(&path)?;
+            }
+            Some(existing) => {
+                return Err(LockError::Held {
+                    path,
+                    owner: existing,
+                })
+            }
+        }
+    }
+
+    match read_owner(&path)? {
+        Some(existing) => Err(LockError::Held {
+            path,
+            owner: existing,
+        }),
+        None => Err(LockError::Io(io::Error::other(format!(
+            "lock {} changed hands repeatedly",
+            path.display()
+        )))),
+    }
+}
+
+/// Acquires the lock, exiting the process with [`LOCK_HELD_EXIT_CODE`] if
+/// another run holds it.
+pub fn acquire_or_exit(dir: &Path, tool: &str) -> io::Result<LockGuard> {
+    match acquire(dir, tool) {
+        Ok(guard) => Ok(guard),
+        Err(e @ LockError::Held { .. }) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(LOCK_HELD_EXIT_CODE);
+        }
+        Err(LockError::Io(e)) => Err(e),
+    }
+}
+
+/// Distinguishes the temp files of concurrent `try_create` calls in one process.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Atomically creates the lock file; returns `false` if it already exists.
+fn try_create(path: &Path, owner: &LockOwner) -> io::Result<bool> {
+    let mut temp_path = path.as_os_str().to_owned();
+    let counter = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    temp_path.push(format!(".{}.{}", owner.pid, counter));
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(&temp_path, serde_json::to_string(owner)?)?;
+    let result = fs::hard_link(&temp_path, path);
+    fs::remove_file(&temp_path)?;
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the owner of an existing lock; `None` if the lock disappeared.
+///
+/// Unreadable content is reported as an owner with pid 0, which is stale.
+fn read_owner(path: &Path) -> io::Result<Option<LockOwner>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content).unwrap_or(LockOwner {
+            pid: 0,
+            hostname: hostname(),
+            tool: "<unreadable lock file>".to_string(),
+            acquired_at: 0,
+        }))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// A lock is stale if it was taken on this host by a process that no longer exists.
+fn is_stale(owner: &LockOwner, local_hostname: &str) -> bool {
+    owner.hostname == local_hostname && !process_exists(owner.pid)
+}
+
+/// Checks for a live process via procfs; assumes it exists where procfs is unavailable.
+fn process_exists(pid: u32) -> bool {
+    if pid == 0 {
+        return false;
+    }
+    if !Path::new("/proc/self").exists() {
+        return true;
+    }
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_foreign_lock(dir: &Path, pid: u32, hostname: &str) {
+        let owner = LockOwner {
+            pid,
+            hostname: hostname.to_string(),
+            tool: "other_tool".to_string(),
+            acquired_at: 0,
+        };
+        fs::write(
+            dir.join(LOCK_FILE_NAME),
+            serde_json::to_string(&owner).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        {
+            let guard = acquire(dir, "test").unwrap();
+            let owner = read_owner(guard.path()).unwrap().unwrap();
+            assert_eq!(owner.pid, std::process::id());
+            assert_eq!(owner.tool, "test");
+        }
+        assert!(!dir.join(LOCK_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_second_acquire_is_held() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let guard = acquire(dir, "first").unwrap();
+        match acquire(dir, "second") {
+            Err(LockError::Held { owner, .. }) => assert_eq!(owner.tool, "first"),
+            other => panic!("expected held lock, got {:?}", other),
+        }
+        drop(guard);
+    }
+
+    #[test]
+    fn test_concurrent_acquire_in_one_process() {
+        let tmp = tempfile::tempdir().unwrap();
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let attempts: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| acquire(tmp.path(), "test")))
+                .collect();
+            attempts.into_iter().map(|a| a.join().unwrap()).collect()
+        });
+        // One attempt gets the lock, the others find it held rather than failing
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Ok(_) | Err(LockError::Held { .. }))));
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        // pid 0 never identifies a live pipeline process
+        write_foreign_lock(dir, 0, &hostname());
+        let guard = acquire(dir, "test").unwrap();
+        assert_eq!(
+            read_owner(guard.path()).unwrap().unwrap().pid,
+            std::process::id()
+        );
+        drop(guard);
+    }
+
+    #[test]
+    fn test_lock_from_other_host_is_not_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        write_foreign_lock(dir, 0, "some-other-host.example.org");
+        assert!(matches!(acquire(dir, "test"), Err(LockError::Held { .. })));
+    }
+
+    #[test]
+    fn test_live_local_process_is_not_stale() {
+        let owner = LockOwner {
+            pid: std::process::id(),
+            hostname: hostname(),
+            tool: "test".to_string(),
+            acquired_at: 0,
+        };
+        assert!(!is_stale(&owner, &hostname()));
+    }
+}
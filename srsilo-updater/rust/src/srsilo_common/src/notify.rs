@@ -0,0 +1,315 @@
+//! Optional webhook notifications.
+//!
+//! When a webhook URL is configured (`--webhook-url` or `SRSILO_WEBHOOK_URL`),
+//! the tools POST a JSON [`Event`] for detected changes and for failures, so
+//! they reach a human outside the journal. Delivery is best effort: a failed
+//! POST is logged and never fails the tool.
+//!
+//! The HTTP client is behind cargo features, so tools built without them do
+//! not pull in reqwest: `webhook` for the blocking [`Notifier::send`] of the
+//! sync tools, `webhook-async` for [`Notifier::send_async`] inside a tokio
+//! runtime. Without the feature, a configured URL only logs a warning.
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use std::panic;
+use std::thread;
+#[cfg(any(feature = "webhook", feature = "webhook-async"))]
+use std::time::Duration;
+
+/// Environment variable the tools read the webhook URL from.
+pub const WEBHOOK_URL_ENV: &str = "SRSILO_WEBHOOK_URL";
+
+#[cfg(any(feature = "webhook", feature = "webhook-async"))]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// New data or revocations were detected
+    Changes,
+    /// A tool failed
+    Error,
+}
+
+/// JSON payload posted to the webhook.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub tool: String,
+    pub organism: Option<String>,
+    pub run_id: Option<String>,
+    /// Unix time the event was created
+    pub timestamp: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+/// Publishes events to the configured webhook; does nothing without a URL.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    url: Option<String>,
+    tool: String,
+    organism: Option<String>,
+    run_id: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(url: Option<&str>, tool: &str) -> Self {
+        Notifier {
+            url: url.filter(|u| !u.is_empty()).map(str::to_string),
+            tool: tool.to_string(),
+            organism: None,
+            run_id: None,
+        }
+    }
+
+    pub fn with_organism(mut self, organism: &str) -> Self {
+        self.organism = Some(organism.to_string());
+        self
+    }
+
+    pub fn with_run_id(mut self, run_id: &str) -> Self {
+        self.run_id = Some(run_id.to_string());
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some()
+    }
+
+    pub fn event(&self, kind: EventKind, message: &str, details: Value) -> Event {
+        Event {
+            kind,
+            tool: self.tool.clone(),
+            organism: self.organism.clone(),
+            run_id: self.run_id.clone(),
+            timestamp: Utc::now().timestamp(),
+            message: message.to_string(),
+            details,
+        }
+    }
+
+    /// Posts the event, logging a warning if delivery fails. Blocks, so it
+    /// must not be called inside an async runtime; use [`Notifier::send_async`] there.
+    pub fn send(&self, event: &Event) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        #[cfg(feature = "webhook")]
+        let result = post_event(url, event);
+        #[cfg(not(feature = "webhook"))]
+        let result = missing_feature(url, event, "webhook");
+        if let Err(e) = result {
+            eprintln!("WARNING: Failed to send webhook notification: {}", e);
+        }
+    }
+
+    /// Like [`Notifier::send`], for the tools running in a tokio runtime.
+    pub async fn send_async(&self, event: &Event) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        #[cfg(feature = "webhook-async")]
+        let result = post_event_async(url, event).await;
+        #[cfg(not(feature = "webhook-async"))]
+        let result = missing_feature(url, event, "webhook-async");
+        if let Err(e) = result {
+            eprintln!("WARNING: Failed to send webhook notification: {}", e);
+        }
+    }
+
+    pub fn changes(&self, message: &str, details: Value) {
+        self.send(&self.event(EventKind::Changes, message, details));
+    }
+
+    pub fn error(&self, message: &str) {
+        self.send(&self.event(EventKind::Error, message, Value::Null));
+    }
+
+    pub async fn changes_async(&self, message: &str, details: Value) {
+        self.send_async(&self.event(EventKind::Changes, message, details))
+            .await;
+    }
+
+    pub async fn error_async(&self, message: &str) {
+        self.send_async(&self.event(EventKind::Error, message, Value::Null))
+            .await;
+    }
+
+    /// Reports panics (failed assertions in the sync tools) as error events,
+    /// after the default panic output. Needs the `webhook` feature.
+    ///
+    /// The event is sent from its own thread, as the panic may happen on a
+    /// thread of an async runtime.
+    pub fn report_panics(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let notifier = self.clone();
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous_hook(info);
+            let event = notifier.event(
+                EventKind::Error,
+                &format!("panicked: {}", info),
+                Value::Null,
+            );
+            let notifier = notifier.clone();
+            let _ = thread::spawn(move || notifier.send(&event)).join();
+        }));
+    }
+}
+
+#[cfg(not(all(feature = "webhook", feature = "webhook-async")))]
+fn missing_feature(url: &str, _event: &Event, feature: &str) -> Result<(), String> {
+    Err(format!(
+        "not posting to {}: built without the {} feature",
+        url, feature
+    ))
+}
+
+/// Posts `event` as JSON to `url`, blocking until the webhook answered.
+#[cfg(feature = "webhook")]
+pub fn post_event(url: &str, event: &Event) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .post(url)
+        .json(event)
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("webhook returned {}", response.status()));
+    }
+    Ok(())
+}
+
+/// Posts `event` as JSON to `url` with the async client.
+#[cfg(feature = "webhook-async")]
+pub async fn post_event_async(url: &str, event: &Event) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .post(url)
+        .json(event)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("webhook returned {}", response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "webhook", feature = "webhook-async"))]
+    use std::io::{BufRead, BufReader, Read, Write};
+    #[cfg(any(feature = "webhook", feature = "webhook-async"))]
+    use std::net::TcpListener;
+
+    /// Accepts one request on a local port and answers with `status`.
+    ///
+    /// Returns the webhook URL and a handle yielding the received JSON body.
+    #[cfg(any(feature = "webhook", feature = "webhook-async"))]
+    fn listen_once(status: &'static str) -> (String, thread::JoinHandle<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            serde_json::from_slice(&body).unwrap()
+        });
+
+        (url, handle)
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_post_event_to_local_listener() {
+        let (url, handle) = listen_once("200 OK");
+        let notifier = Notifier::new(Some(&url), "check_new_data")
+            .with_organism("covid")
+            .with_run_id("run-1");
+        let event = notifier.event(
+            EventKind::Changes,
+            "1 revocation",
+            serde_json::json!({"revocations": 1}),
+        );
+
+        post_event(&url, &event).unwrap();
+
+        let received = handle.join().unwrap();
+        assert_eq!(received["kind"], "changes");
+        assert_eq!(received["tool"], "check_new_data");
+        assert_eq!(received["organism"], "covid");
+        assert_eq!(received["run_id"], "run-1");
+        assert_eq!(received["details"]["revocations"], 1);
+    }
+
+    #[cfg(feature = "webhook-async")]
+    #[tokio::test]
+    async fn test_post_event_async_to_local_listener() {
+        let (url, handle) = listen_once("200 OK");
+        let event = Notifier::new(Some(&url), "check_new_data")
+            .with_organism("rsva")
+            .event(EventKind::Error, "failed", Value::Null);
+
+        post_event_async(&url, &event).await.unwrap();
+
+        let received = handle.join().unwrap();
+        assert_eq!(received["kind"], "error");
+        assert_eq!(received["organism"], "rsva");
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_post_event_reports_error_status() {
+        let (url, handle) = listen_once("500 Internal Server Error");
+        let event = Notifier::new(Some(&url), "merge_sorted_chunks").event(
+            EventKind::Error,
+            "failed",
+            Value::Null,
+        );
+
+        let err = post_event(&url, &event).unwrap_err();
+        assert!(err.contains("500"));
+        assert!(handle.join().unwrap().get("details").is_none());
+    }
+
+    #[test]
+    fn test_notifier_without_url_is_disabled() {
+        assert!(!Notifier::new(None, "fetch_silo_data").is_enabled());
+        assert!(!Notifier::new(Some(""), "fetch_silo_data").is_enabled());
+    }
+}
@@ -0,0 +1,83 @@
+//! Byte sizes on the command line (`512M`, `2G`, `1073741824`).
+
+/// Parses a byte size with an optional binary suffix (`K`, `M`, `G`, `T`,
+/// optionally followed by `B` or `iB`). Fractions are allowed with a suffix.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let invalid = || {
+        format!(
+            "invalid size '{}' (expected e.g. 1073741824, 512M, 2G)",
+            value
+        )
+    };
+
+    let upper = value.to_ascii_uppercase();
+    let number_end = upper
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(upper.len());
+    let (number, unit) = upper.split_at(number_end);
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole.checked_mul(multiplier).ok_or_else(invalid);
+    }
+    match number.parse::<f64>() {
+        Ok(fraction) if multiplier > 1 && fraction.is_finite() && fraction >= 0.0 => {
+            Ok((fraction * multiplier as f64) as u64)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Formats a byte size for log output, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1073741824"), Ok(1 << 30));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("64kb"), Ok(64 << 10));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+    }
+
+    #[test]
+    fn test_parse_size_rejects_garbage() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("1.5").is_err());
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("-1G").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 << 29), "1.5 GiB");
+    }
+}
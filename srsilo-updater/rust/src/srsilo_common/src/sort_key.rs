@@ -0,0 +1,707 @@
+//! Typed, composite sort keys shared by the split and merge tools.
+//!
+//! A key is given as `<json pointer>[:<type>[:asc|desc]]`, e.g. `/main/offset:int`
+//! or `/samplingDate:date:desc`. The type defaults to `int` and the direction to
+//! `asc`. Several keys compare lexicographically in the order given.
+//!
+//! A [`KeyExtractor`] applies the `--missing-key` policy to records whose key
+//! field is missing or `null`, identically in both tools, and counts them.
+
+use crate::json_pointer::PointerSet;
+use chrono::NaiveDate;
+use serde_json::value::RawValue;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Int,
+    Float,
+    String,
+    /// A `YYYY-MM-DD` string
+    Date,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KeyType::Int => "int",
+            KeyType::Float => "float",
+            KeyType::String => "string",
+            KeyType::Date => "date",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+/// One `--sort-key` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKeySpec {
+    pub pointer: String,
+    pub key_type: KeyType,
+    pub direction: Direction,
+}
+
+impl SortKeySpec {
+    /// The key used by `--sort-field-path`: an ascending integer.
+    pub fn int(pointer: &str) -> Self {
+        SortKeySpec {
+            pointer: pointer.to_string(),
+            key_type: KeyType::Int,
+            direction: Direction::Asc,
+        }
+    }
+}
+
+impl FromStr for SortKeySpec {
+    type Err = String;
+
+    /// Options are split off from the right, so the pointer itself may contain `:`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut pointer = value;
+        let mut direction = Direction::Asc;
+        let mut key_type = KeyType::Int;
+
+        if let Some((rest, option)) = pointer.rsplit_once(':') {
+            let parsed_direction = match option {
+                "asc" => Some(Direction::Asc),
+                "desc" => Some(Direction::Desc),
+                _ => None,
+            };
+            if let Some(parsed) = parsed_direction {
+                direction = parsed;
+                pointer = rest;
+            }
+        }
+        if let Some((rest, option)) = pointer.rsplit_once(':') {
+            key_type = match option {
+                "int" => KeyType::Int,
+                "float" => KeyType::Float,
+                "string" => KeyType::String,
+                "date" => KeyType::Date,
+                _ => {
+                    return Err(format!(
+                        "invalid sort key '{}': unknown type '{}' (expected int, float, string or date)",
+                        value, option
+                    ))
+                }
+            };
+            pointer = rest;
+        }
+
+        if !pointer.starts_with('/') {
+            return Err(format!(
+                "invalid sort key '{}': expected a JSON pointer such as /main/offset",
+                value
+            ));
+        }
+        Ok(SortKeySpec {
+            pointer: pointer.to_string(),
+            key_type,
+            direction,
+        })
+    }
+}
+
+/// Combines `--sort-key` arguments with the older `--sort-field-path`.
+pub fn resolve_sort_keys(
+    sort_keys: &[SortKeySpec],
+    sort_field_path: Option<&str>,
+) -> Vec<SortKeySpec> {
+    match sort_field_path {
+        Some(path) => vec![SortKeySpec::int(path)],
+        None => sort_keys.to_vec(),
+    }
+}
+
+/// A typed sort field value.
+#[derive(Debug, Clone)]
+pub enum KeyValue {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Date(NaiveDate),
+}
+
+impl KeyValue {
+    fn rank(&self) -> u8 {
+        match self {
+            KeyValue::Int(_) => 0,
+            KeyValue::Float(_) => 1,
+            KeyValue::String(_) => 2,
+            KeyValue::Date(_) => 3,
+        }
+    }
+}
+
+impl Ord for KeyValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (KeyValue::Int(a), KeyValue::Int(b)) => a.cmp(b),
+            (KeyValue::Float(a), KeyValue::Float(b)) => a.total_cmp(b),
+            (KeyValue::String(a), KeyValue::String(b)) => a.cmp(b),
+            (KeyValue::Date(a), KeyValue::Date(b)) => a.cmp(b),
+            // Keys from the same specs always have matching types
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for KeyValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for KeyValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for KeyValue {}
+
+/// One field of a [`SortKey`], ordered by its direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyField {
+    Asc(KeyValue),
+    Desc(KeyValue),
+    /// A missing or null value sorted before all others
+    First,
+    /// A missing or null value sorted after all others
+    Last,
+}
+
+impl Ord for KeyField {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (KeyField::First, KeyField::First) | (KeyField::Last, KeyField::Last) => {
+                Ordering::Equal
+            }
+            (KeyField::First, _) | (_, KeyField::Last) => Ordering::Less,
+            (_, KeyField::First) | (KeyField::Last, _) => Ordering::Greater,
+            (KeyField::Asc(a), KeyField::Asc(b)) => a.cmp(b),
+            (KeyField::Desc(a), KeyField::Desc(b)) => b.cmp(a),
+            (KeyField::Asc(_), KeyField::Desc(_)) => Ordering::Less,
+            (KeyField::Desc(_), KeyField::Asc(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for KeyField {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The composite sort key of a record.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortKey(pub Vec<KeyField>);
+
+impl SortKey {
+    /// Heap bytes used by the key, for memory accounting.
+    pub fn heap_size(&self) -> usize {
+        let strings: usize = self
+            .0
+            .iter()
+            .map(|field| match field {
+                KeyField::Asc(KeyValue::String(s)) | KeyField::Desc(KeyValue::String(s)) => {
+                    s.capacity()
+                }
+                _ => 0,
+            })
+            .sum();
+        self.0.capacity() * std::mem::size_of::<KeyField>() + strings
+    }
+}
+
+/// Formats the key for messages, e.g. `(42, "r1", missing)`.
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, field) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match field {
+                KeyField::Asc(value) | KeyField::Desc(value) => match value {
+                    KeyValue::Int(v) => write!(f, "{}", v)?,
+                    KeyValue::Float(v) => write!(f, "{}", v)?,
+                    KeyValue::String(v) => write!(f, "{:?}", v)?,
+                    KeyValue::Date(v) => write!(f, "{}", v)?,
+                },
+                KeyField::First | KeyField::Last => write!(f, "missing")?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug)]
+pub enum SortKeyError {
+    Json(serde_json::Error),
+    Missing {
+        pointer: String,
+    },
+    Null {
+        pointer: String,
+    },
+    WrongType {
+        pointer: String,
+        expected: KeyType,
+        found: String,
+    },
+}
+
+impl fmt::Display for SortKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortKeyError::Json(e) => write!(f, "Invalid JSON line: {}", e),
+            SortKeyError::Missing { pointer } => write!(f, "Did not find field {}", pointer),
+            SortKeyError::Null { pointer } => write!(f, "Field {} is null", pointer),
+            SortKeyError::WrongType {
+                pointer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Field {} is not of type {}: {}",
+                pointer, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SortKeyError {}
+
+impl From<SortKeyError> for std::io::Error {
+    fn from(e: SortKeyError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// What to do with a record whose sort key field is missing or `null`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MissingKeyPolicy {
+    /// Fail the run
+    Error,
+    /// Sort the record before all others
+    First,
+    /// Sort the record after all others
+    Last,
+    /// Leave the record out of the output
+    Drop,
+    /// Use this value instead, parsed as the key's type
+    Default(String),
+}
+
+impl FromStr for MissingKeyPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(MissingKeyPolicy::Error),
+            "first" => Ok(MissingKeyPolicy::First),
+            "last" => Ok(MissingKeyPolicy::Last),
+            "drop" => Ok(MissingKeyPolicy::Drop),
+            _ => match value.strip_prefix("default=") {
+                Some(default) => Ok(MissingKeyPolicy::Default(default.to_string())),
+                None => Err(format!(
+                    "invalid missing key policy '{}' (expected error, first, last, drop or default=<value>)",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+/// Extracts sort keys, applying the missing key policy and counting the
+/// records it was applied to.
+#[derive(Debug)]
+pub struct KeyExtractor {
+    specs: Vec<SortKeySpec>,
+    policy: MissingKeyPolicy,
+    /// The `default=` value for each key, typed
+    defaults: Vec<Option<KeyValue>>,
+    /// The key pointers, followed by the extra pointer if there is one
+    pointers: PointerSet,
+    has_extra_pointer: bool,
+    missing: AtomicU64,
+    null: AtomicU64,
+}
+
+impl KeyExtractor {
+    /// Errors if a `default=` value does not parse as the type of every key.
+    pub fn new(specs: Vec<SortKeySpec>, policy: MissingKeyPolicy) -> Result<Self, String> {
+        let defaults = specs
+            .iter()
+            .map(|spec| match &policy {
+                MissingKeyPolicy::Default(text) => {
+                    parse_default(text, spec.key_type).map(Some).ok_or_else(|| {
+                        format!(
+                            "default sort key value '{}' is not a valid {} for {}",
+                            text, spec.key_type, spec.pointer
+                        )
+                    })
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(KeyExtractor {
+            pointers: PointerSet::new(specs.iter().map(|spec| &spec.pointer)),
+            has_extra_pointer: false,
+            specs,
+            policy,
+            defaults,
+            missing: AtomicU64::new(0),
+            null: AtomicU64::new(0),
+        })
+    }
+
+    /// Also looks up `pointer` in the same pass over each line, for
+    /// [`KeyExtractor::extract_with_extra`]. The policy does not apply to it.
+    pub fn with_extra_pointer(mut self, pointer: &str) -> Self {
+        let pointers = self.specs.iter().map(|spec| spec.pointer.as_str());
+        self.pointers = PointerSet::new(pointers.chain([pointer]));
+        self.has_extra_pointer = true;
+        self
+    }
+
+    /// Reads the sort key of an NDJSON line without parsing the rest of the record.
+    ///
+    /// Returns `None` if the record is dropped by the policy.
+    pub fn extract(&self, line: &str) -> Result<Option<SortKey>, SortKeyError> {
+        Ok(self.extract_with_extra(line)?.map(|(key, _)| key))
+    }
+
+    /// Like [`KeyExtractor::extract`], also returning the raw value at the extra
+    /// pointer (`None` if it is missing or none was set). All key fields and the
+    /// extra value are found in one pass over the line.
+    pub fn extract_with_extra<'a>(
+        &self,
+        line: &'a str,
+    ) -> Result<Option<(SortKey, Option<&'a RawValue>)>, SortKeyError> {
+        let mut raw_values = self.pointers.extract(line).map_err(SortKeyError::Json)?;
+        let extra = if self.has_extra_pointer {
+            raw_values.pop().flatten()
+        } else {
+            None
+        };
+        let mut fields = Vec::with_capacity(self.specs.len());
+        let mut absent: Option<&AtomicU64> = None;
+
+        for ((spec, default), raw) in self.specs.iter().zip(&self.defaults).zip(raw_values) {
+            let value = match read_value(raw, spec) {
+                Ok(value) => value,
+                Err(e @ (SortKeyError::Missing { .. } | SortKeyError::Null { .. })) => {
+                    // A record counts as missing if any of its key fields is
+                    if absent.is_none() || matches!(e, SortKeyError::Missing { .. }) {
+                        absent = Some(match e {
+                            SortKeyError::Missing { .. } => &self.missing,
+                            _ => &self.null,
+                        });
+                    }
+                    match &self.policy {
+                        MissingKeyPolicy::Error => return Err(e),
+                        MissingKeyPolicy::First => {
+                            fields.push(KeyField::First);
+                            continue;
+                        }
+                        MissingKeyPolicy::Last => {
+                            fields.push(KeyField::Last);
+                            continue;
+                        }
+                        MissingKeyPolicy::Drop => {
+                            if let Some(counter) = absent {
+                                counter.fetch_add(1, AtomicOrdering::Relaxed);
+                            }
+                            return Ok(None);
+                        }
+                        MissingKeyPolicy::Default(_) => default.clone().unwrap(),
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+            fields.push(match spec.direction {
+                Direction::Asc => KeyField::Asc(value),
+                Direction::Desc => KeyField::Desc(value),
+            });
+        }
+
+        if let Some(counter) = absent {
+            counter.fetch_add(1, AtomicOrdering::Relaxed);
+        }
+        Ok(Some((SortKey(fields), extra)))
+    }
+
+    /// Number of records with a missing and with a null key field so far.
+    pub fn counts(&self) -> (u64, u64) {
+        (
+            self.missing.load(AtomicOrdering::Relaxed),
+            self.null.load(AtomicOrdering::Relaxed),
+        )
+    }
+
+    /// A line for the log if the policy was applied to any record.
+    pub fn summary(&self) -> Option<String> {
+        let (missing, null) = self.counts();
+        if missing + null == 0 {
+            return None;
+        }
+        let action = match &self.policy {
+            MissingKeyPolicy::Error => "rejected".to_string(),
+            MissingKeyPolicy::First => "sorted first".to_string(),
+            MissingKeyPolicy::Last => "sorted last".to_string(),
+            MissingKeyPolicy::Drop => "dropped".to_string(),
+            MissingKeyPolicy::Default(value) => format!("sorted as {}", value),
+        };
+        Some(format!(
+            "{} record(s) with a missing and {} with a null sort key {}",
+            missing, null, action
+        ))
+    }
+}
+
+fn read_value(raw: Option<&RawValue>, spec: &SortKeySpec) -> Result<KeyValue, SortKeyError> {
+    let raw = raw.ok_or_else(|| SortKeyError::Missing {
+        pointer: spec.pointer.clone(),
+    })?;
+    parse_value(raw.get(), spec)
+}
+
+fn parse_default(text: &str, key_type: KeyType) -> Option<KeyValue> {
+    match key_type {
+        KeyType::Int => text.parse().ok().map(KeyValue::Int),
+        KeyType::Float => text.parse().ok().map(KeyValue::Float),
+        KeyType::String => Some(KeyValue::String(text.to_string())),
+        KeyType::Date => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .map(KeyValue::Date),
+    }
+}
+
+fn parse_value(raw: &str, spec: &SortKeySpec) -> Result<KeyValue, SortKeyError> {
+    if raw == "null" {
+        return Err(SortKeyError::Null {
+            pointer: spec.pointer.clone(),
+        });
+    }
+    let value = match spec.key_type {
+        KeyType::Int => raw.parse::<i64>().ok().map(KeyValue::Int),
+        KeyType::Float => serde_json::from_str::<f64>(raw).ok().map(KeyValue::Float),
+        KeyType::String => serde_json::from_str::<String>(raw)
+            .ok()
+            .map(KeyValue::String),
+        KeyType::Date => serde_json::from_str::<String>(raw)
+            .ok()
+            .and_then(|s| NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok())
+            .map(KeyValue::Date),
+    };
+    value.ok_or_else(|| SortKeyError::WrongType {
+        pointer: spec.pointer.clone(),
+        expected: spec.key_type,
+        found: raw.chars().take(100).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(keys: &str) -> Vec<SortKeySpec> {
+        keys.split(',').map(|k| k.parse().unwrap()).collect()
+    }
+
+    fn extract_sort_key(line: &str, specs: &[SortKeySpec]) -> Result<SortKey, SortKeyError> {
+        KeyExtractor::new(specs.to_vec(), MissingKeyPolicy::Error)
+            .unwrap()
+            .extract(line)
+            .map(Option::unwrap)
+    }
+
+    fn extractor(keys: &str, policy: &str) -> KeyExtractor {
+        KeyExtractor::new(specs(keys), policy.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            "/main/offset".parse::<SortKeySpec>(),
+            Ok(SortKeySpec::int("/main/offset"))
+        );
+        assert_eq!(
+            "/samplingDate:date:desc".parse::<SortKeySpec>(),
+            Ok(SortKeySpec {
+                pointer: "/samplingDate".to_string(),
+                key_type: KeyType::Date,
+                direction: Direction::Desc,
+            })
+        );
+        let spec: SortKeySpec = "/a:b:string:asc".parse().unwrap();
+        assert_eq!(spec.pointer, "/a:b");
+        assert_eq!(spec.key_type, KeyType::String);
+
+        assert!("/a:bool".parse::<SortKeySpec>().is_err());
+        assert!("main/offset:int".parse::<SortKeySpec>().is_err());
+    }
+
+    #[test]
+    fn test_extract_typed_values() {
+        let line =
+            r#"{"main":{"offset":42},"readId":"r\"1","score":1.5,"samplingDate":"2025-07-03"}"#;
+        let key = extract_sort_key(
+            line,
+            &specs("/main/offset:int,/readId:string,/score:float,/samplingDate:date"),
+        )
+        .unwrap();
+        assert_eq!(
+            key,
+            SortKey(vec![
+                KeyField::Asc(KeyValue::Int(42)),
+                KeyField::Asc(KeyValue::String("r\"1".to_string())),
+                KeyField::Asc(KeyValue::Float(1.5)),
+                KeyField::Asc(KeyValue::Date(NaiveDate::from_ymd_opt(2025, 7, 3).unwrap())),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_display_sort_key() {
+        let key = extract_sort_key(
+            r#"{"o":42,"id":"r1","d":"2025-07-03"}"#,
+            &specs("/o:int:desc,/id:string,/d:date"),
+        )
+        .unwrap();
+        assert_eq!(key.to_string(), r#"(42, "r1", 2025-07-03)"#);
+        let missing = extractor("/x", "first").extract("{}").unwrap().unwrap();
+        assert_eq!(missing.to_string(), "(missing)");
+    }
+
+    #[test]
+    fn test_composite_ordering_with_directions() {
+        let keys = specs("/date:date:desc,/offset:int");
+        let key = |line: &str| extract_sort_key(line, &keys).unwrap();
+        let a = key(r#"{"date":"2025-07-03","offset":5}"#);
+        let b = key(r#"{"date":"2025-07-03","offset":7}"#);
+        let c = key(r#"{"date":"2025-06-30","offset":1}"#);
+        assert!(a < b);
+        assert!(b < c);
+    }
+
+    #[test]
+    fn test_float_ordering_is_total() {
+        let keys = specs("/x:float");
+        let key = |line: &str| extract_sort_key(line, &keys).unwrap();
+        assert!(key(r#"{"x":-1e3}"#) < key(r#"{"x":2}"#));
+        assert!(key(r#"{"x":2}"#) < key(r#"{"x":2.5}"#));
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        let keys = specs("/main/offset:int");
+        assert!(matches!(
+            extract_sort_key(r#"{"main":{}}"#, &keys),
+            Err(SortKeyError::Missing { .. })
+        ));
+        assert!(matches!(
+            extract_sort_key(r#"{"main":{"offset":null}}"#, &keys),
+            Err(SortKeyError::Null { .. })
+        ));
+        assert!(matches!(
+            extract_sort_key(r#"{"main":{"offset":"7"}}"#, &keys),
+            Err(SortKeyError::WrongType { .. })
+        ));
+        assert!(matches!(
+            extract_sort_key(r#"{"main":{"offset":1.5}}"#, &keys),
+            Err(SortKeyError::WrongType { .. })
+        ));
+        assert!(matches!(
+            extract_sort_key(r#"{"d":"03.07.2025"}"#, &specs("/d:date")),
+            Err(SortKeyError::WrongType { .. })
+        ));
+        assert!(matches!(
+            extract_sort_key("{", &keys),
+            Err(SortKeyError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!("drop".parse(), Ok(MissingKeyPolicy::Drop));
+        assert_eq!(
+            "default=-1".parse(),
+            Ok(MissingKeyPolicy::Default("-1".to_string()))
+        );
+        assert!("ignore".parse::<MissingKeyPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_first_and_last_policies() {
+        let first = extractor("/x:int:desc", "first");
+        let key = |line: &str| first.extract(line).unwrap().unwrap();
+        assert!(key(r#"{"x":null}"#) < key(r#"{"x":5}"#));
+        assert!(key(r#"{}"#) < key(r#"{"x":-5}"#));
+        assert_eq!(first.counts(), (1, 1));
+
+        let last = extractor("/x:int,/y:string", "last");
+        let key = |line: &str| last.extract(line).unwrap().unwrap();
+        assert!(key(r#"{"x":5,"y":"a"}"#) < key(r#"{"x":null,"y":"a"}"#));
+        // The other fields still break ties
+        assert!(key(r#"{"y":"a"}"#) < key(r#"{"y":"b"}"#));
+        assert_eq!(last.counts(), (2, 1));
+        assert_eq!(
+            last.summary().unwrap(),
+            "2 record(s) with a missing and 1 with a null sort key sorted last"
+        );
+    }
+
+    #[test]
+    fn test_drop_and_default_policies() {
+        let drop = extractor("/x:int", "drop");
+        assert!(drop.extract(r#"{"x":null}"#).unwrap().is_none());
+        assert!(drop.extract(r#"{"x":1}"#).unwrap().is_some());
+        assert_eq!(drop.counts(), (0, 1));
+
+        let default = extractor("/x:int", "default=7");
+        assert_eq!(
+            default.extract(r#"{}"#).unwrap(),
+            default.extract(r#"{"x":7}"#).unwrap()
+        );
+        assert!(KeyExtractor::new(specs("/d:date"), "default=7".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_extra_pointer() {
+        let extractor = extractor("/o:int", "drop").with_extra_pointer("/id");
+        let (key, extra) = extractor
+            .extract_with_extra(r#"{"id":"r1","o":2}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, SortKey(vec![KeyField::Asc(KeyValue::Int(2))]));
+        assert_eq!(extra.map(RawValue::get), Some(r#""r1""#));
+        let (_, extra) = extractor.extract_with_extra(r#"{"o":2}"#).unwrap().unwrap();
+        assert!(extra.is_none());
+        assert!(extractor
+            .extract_with_extra(r#"{"id":"r1"}"#)
+            .unwrap()
+            .is_none());
+        assert!(extractor.extract(r#"{"o":3}"#).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_error_policy_and_wrong_types() {
+        let error = extractor("/x:int", "error");
+        assert!(error.extract(r#"{}"#).is_err());
+        assert_eq!(error.summary(), None);
+        // The policy only covers missing and null values
+        assert!(extractor("/x:int", "last").extract(r#"{"x":"7"}"#).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Pipeline state file (`.last_update` / `.next_timestamp`).
+//!
+//! The state is a versioned JSON document. `check_new_data` reads the
+//! promoted state from `.last_update` and writes the pending state for the
+//! current run to `.next_timestamp`; the orchestrator promotes it once the
+//! pipeline succeeded.
+//!
+//! Files written by older versions contain a bare Unix timestamp. They are
+//! migrated transparently on read.
+
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Current version of the state file format.
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// Placeholder for `tool_version` and `run_id` of migrated legacy files.
+const LEGACY_MARKER: &str = "legacy";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PipelineState {
+    /// Version of the state file format
+    pub format_version: u32,
+
+    /// Maximum submittedAtTimestamp included in the index
+    pub last_processed_timestamp: i64,
+
+    /// Sample ids included in the index, with their version where known
+    #[serde(default)]
+    pub samples: BTreeMap<String, Option<i64>>,
+
+    /// Sampling date window actually fetched for the index (recorded by `fetch_silo_data`)
+    #[serde(default)]
+    pub fetch_window: Option<FetchWindow>,
+
+    /// Name and version of the tool that last wrote the state
+    pub tool_version: String,
+
+    /// Identifier of the pipeline run that produced the state
+    pub run_id: String,
+}
+
+impl PipelineState {
+    pub fn new(last_processed_timestamp: i64, tool_version: &str, run_id: &str) -> Self {
+        PipelineState {
+            format_version: STATE_FORMAT_VERSION,
+            last_processed_timestamp,
+            samples: BTreeMap::new(),
+            fetch_window: None,
+            tool_version: tool_version.to_string(),
+            run_id: run_id.to_string(),
+        }
+    }
+}
+
+/// Sampling dates covered by a fetch.
+///
+/// `fetch_silo_data` walks back from `start_date` to `earliest_allowed` and
+/// stops early once the next date would exceed the read budget; that date is
+/// recorded as `read_budget_cutoff`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FetchWindow {
+    pub start_date: NaiveDate,
+    pub earliest_allowed: NaiveDate,
+    /// Earliest sampling date with data included in the index
+    pub earliest_included: Option<NaiveDate>,
+    /// Latest date left out because of the read budget
+    pub read_budget_cutoff: Option<NaiveDate>,
+}
+
+impl FetchWindow {
+    /// First sampling date a fetch with this window's budget would include,
+    /// given the rolling window start for the next run.
+    ///
+    /// Assumes the next fetch stops no earlier than this one did: the budget
+    /// is spent from the newest date backwards, and new submissions only add
+    /// reads to the dates before the cutoff.
+    pub fn effective_start(&self, rolling_start: NaiveDate) -> NaiveDate {
+        match self.read_budget_cutoff {
+            Some(cutoff) => rolling_start.max(cutoff + Duration::days(1)),
+            None => rolling_start,
+        }
+    }
+}
+
+/// A state read from disk, flagging whether it was migrated from the legacy format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedState {
+    pub state: PipelineState,
+    pub migrated_from_legacy: bool,
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    Corrupted { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Io(e) => write!(f, "Failed to access state file: {}", e),
+            StateError::Corrupted { path, reason } => write!(
+                f,
+                "State file {} is corrupted ({}). Rerun with --recover-state to move it \
+                 aside and rebuild from the rolling window.",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(e: io::Error) -> Self {
+        StateError::Io(e)
+    }
+}
+
+/// Parses the content of a state file, accepting both the JSON and the legacy integer format.
+pub fn parse_state(content: &str) -> std::result::Result<LoadedState, String> {
+    let trimmed = content.trim();
+
+    if trimmed.is_empty() {
+        return Err("file is empty".to_string());
+    }
+
+    if let Ok(timestamp) = trimmed.parse::<i64>() {
+        return Ok(LoadedState {
+            state: PipelineState::new(timestamp, LEGACY_MARKER, LEGACY_MARKER),
+            migrated_from_legacy: true,
+        });
+    }
+
+    let state: PipelineState =
+        serde_json::from_str(trimmed).map_err(|e| format!("invalid JSON: {}", e))?;
+
+    if state.format_version > STATE_FORMAT_VERSION {
+        return Err(format!(
+            "unsupported format version {} (this tool supports up to {})",
+            state.format_version, STATE_FORMAT_VERSION
+        ));
+    }
+
+    Ok(LoadedState {
+        state,
+        migrated_from_legacy: false,
+    })
+}
+
+/// Reads the state file at `path`.
+///
+/// Returns `Ok(None)` if the file does not exist.
+pub fn read_state(path: &Path) -> std::result::Result<Option<LoadedState>, StateError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path)?;
+    parse_state(&content)
+        .map(Some)
+        .map_err(|reason| StateError::Corrupted {
+            path: path.to_path_buf(),
+            reason,
+        })
+}
+
+/// Writes the state atomically (temp file then rename).
+pub fn write_state(path: &Path, state: &PipelineState) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(state)?;
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, content + "\n")?;
+    fs::rename(temp_path, path)
+}
+
+/// Moves a corrupted state file aside so the next run starts fresh.
+///
+/// Returns the path the file was moved to.
+pub fn quarantine_state(path: &Path) -> io::Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".corrupt-{}", Utc::now().timestamp()));
+    let backup = PathBuf::from(backup);
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// Generates an identifier for a pipeline run from the current time and process id.
+pub fn generate_run_id() -> String {
+    format!(
+        "{}-{}",
+        Utc::now().format("%Y%m%dT%H%M%SZ"),
+        std::process::id()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_integer() {
+        let loaded = parse_state("1700000000\n").unwrap();
+        assert!(loaded.migrated_from_legacy);
+        assert_eq!(loaded.state.last_processed_timestamp, 1700000000);
+        assert_eq!(loaded.state.format_version, STATE_FORMAT_VERSION);
+        assert!(loaded.state.samples.is_empty());
+    }
+
+    #[test]
+    fn test_parse_json_state() {
+        let content = r#"{
+            "format_version": 1,
+            "last_processed_timestamp": 1751500000,
+            "samples": {"A1_10": 2, "B2_10": null},
+            "tool_version": "check_new_data 0.1.0",
+            "run_id": "20250703T000000Z-42"
+        }"#;
+        let loaded = parse_state(content).unwrap();
+        assert!(!loaded.migrated_from_legacy);
+        assert_eq!(loaded.state.last_processed_timestamp, 1751500000);
+        assert_eq!(loaded.state.samples.get("A1_10"), Some(&Some(2)));
+        assert_eq!(loaded.state.samples.get("B2_10"), Some(&None));
+        assert_eq!(loaded.state.run_id, "20250703T000000Z-42");
+    }
+
+    #[test]
+    fn test_parse_empty_is_corrupted() {
+        assert_eq!(parse_state("  \n").unwrap_err(), "file is empty");
+    }
+
+    #[test]
+    fn test_parse_garbage_is_corrupted() {
+        assert!(parse_state("17000000xx")
+            .unwrap_err()
+            .contains("invalid JSON"));
+    }
+
+    #[test]
+    fn test_parse_future_format_version() {
+        let content = r#"{"format_version": 99, "last_processed_timestamp": 1,
+            "tool_version": "x", "run_id": "y"}"#;
+        assert!(parse_state(content)
+            .unwrap_err()
+            .contains("unsupported format version 99"));
+    }
+
+    #[test]
+    fn test_parse_state_with_fetch_window() {
+        let content = r#"{
+            "format_version": 1,
+            "last_processed_timestamp": 1751500000,
+            "fetch_window": {
+                "start_date": "2025-07-10",
+                "earliest_allowed": "2025-04-11",
+                "earliest_included": "2025-06-01",
+                "read_budget_cutoff": "2025-05-31"
+            },
+            "tool_version": "fetch_silo_data 0.1.0",
+            "run_id": "run-1"
+        }"#;
+        let window = parse_state(content).unwrap().state.fetch_window.unwrap();
+        assert_eq!(
+            window.read_budget_cutoff,
+            NaiveDate::from_ymd_opt(2025, 5, 31)
+        );
+    }
+
+    #[test]
+    fn test_effective_start() {
+        let date = |d| NaiveDate::from_ymd_opt(2025, 6, d).unwrap();
+        let mut window = FetchWindow {
+            start_date: date(30),
+            earliest_allowed: date(1),
+            earliest_included: Some(date(11)),
+            read_budget_cutoff: Some(date(10)),
+        };
+        // Budget cutoff is later than the rolling window start
+        assert_eq!(window.effective_start(date(2)), date(11));
+        // Rolling window has moved past the cutoff
+        assert_eq!(window.effective_start(date(20)), date(20));
+
+        window.read_budget_cutoff = None;
+        assert_eq!(window.effective_start(date(2)), date(2));
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".last_update");
+        let mut state = PipelineState::new(1751500000, "check_new_data 0.1.0", "run-1");
+        state.samples.insert("A1_10".to_string(), Some(1));
+
+        write_state(&path, &state).unwrap();
+        let loaded = read_state(&path).unwrap().unwrap();
+
+        assert_eq!(loaded.state, state);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn test_read_missing_and_quarantine_corrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".last_update");
+        assert!(read_state(&path).unwrap().is_none());
+
+        fs::write(&path, "").unwrap();
+        assert!(matches!(
+            read_state(&path),
+            Err(StateError::Corrupted { .. })
+        ));
+
+        let backup = quarantine_state(&path).unwrap();
+        assert!(!path.exists());
+        assert!(backup.exists());
+        assert!(read_state(&path).unwrap().is_none());
+    }
+}
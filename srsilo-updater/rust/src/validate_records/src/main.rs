@@ -0,0 +1,211 @@
+//! Validate Records - WisePulse Data Pipeline
+//!
+//! Checks every record of zstd-compressed NDJSON files against the metadata
+//! schema in SILO's `database_config.yaml` (see `schema.rs`), so records SILO
+//! preprocessing would reject are found before sorting instead of at the end
+//! of the pipeline. Files are checked in parallel; the violations are
+//! summarized per file with the line numbers of the first examples.
+//!
+//! Exit codes:
+//! - 0: All records are valid
+//! - 1: At least one record violates the schema
+//! - 2: Error occurred (unreadable file or config)
+
+use clap::Parser;
+use rayon::prelude::*;
+use schema::Schema;
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use zstd::stream::Decoder;
+
+mod schema;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// zstd-compressed NDJSON files to check
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// SILO database config with the metadata schema and primary key
+    #[arg(long)]
+    database_config: PathBuf,
+
+    /// SILO reference genomes; if given, fields that are neither metadata nor
+    /// a sequence are reported as unexpected
+    #[arg(long)]
+    reference_genomes: Option<PathBuf>,
+
+    /// Line numbers to list per kind of violation and file
+    #[arg(long, default_value_t = 3)]
+    max_examples: usize,
+
+    /// URL to POST an error event to as JSON if the check fails to run
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
+}
+
+/// Occurrences of one kind of violation in a file.
+#[derive(Debug, Default, PartialEq)]
+struct ViolationCount {
+    count: u64,
+    /// Line numbers of the first occurrences
+    lines: Vec<u64>,
+}
+
+/// Result of checking one file.
+#[derive(Debug, Default, PartialEq)]
+struct FileReport {
+    records: u64,
+    invalid_records: u64,
+    violations: BTreeMap<String, ViolationCount>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let notifier = Notifier::new(args.webhook_url.as_deref(), "validate_records");
+    notifier.report_panics();
+
+    let exit_code = match run(&args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            notifier.error(&e.to_string());
+            2
+        }
+    };
+    std::process::exit(exit_code);
+}
+
+/// Checks all files; returns whether all records are valid.
+fn run(args: &Args) -> io::Result<bool> {
+    let schema = Schema::load(&args.database_config, args.reference_genomes.as_deref())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let reports = args
+        .inputs
+        .par_iter()
+        .map(|input| validate_file(input, &schema, args.max_examples))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let (mut records, mut invalid_records) = (0, 0);
+    for (input, report) in args.inputs.iter().zip(&reports) {
+        print_report(input, report);
+        records += report.records;
+        invalid_records += report.invalid_records;
+    }
+    println!(
+        "{} of {} record(s) in {} file(s) violate the schema",
+        invalid_records,
+        records,
+        reports.len()
+    );
+    Ok(invalid_records == 0)
+}
+
+fn validate_file(path: &Path, schema: &Schema, max_examples: usize) -> io::Result<FileReport> {
+    let with_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+    let file = File::open(path).map_err(with_path)?;
+    validate(
+        BufReader::new(Decoder::new(file).map_err(with_path)?),
+        schema,
+        max_examples,
+    )
+    .map_err(with_path)
+}
+
+fn validate<R: BufRead>(reader: R, schema: &Schema, max_examples: usize) -> io::Result<FileReport> {
+    let mut report = FileReport::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        report.records += 1;
+        let violations = schema.check(&line);
+        if violations.is_empty() {
+            continue;
+        }
+        report.invalid_records += 1;
+        for violation in violations {
+            let entry = report.violations.entry(violation).or_default();
+            entry.count += 1;
+            if entry.lines.len() < max_examples {
+                entry.lines.push(index as u64 + 1);
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn print_report(path: &Path, report: &FileReport) {
+    if report.invalid_records == 0 {
+        println!("{}: {} record(s), valid", path.display(), report.records);
+        return;
+    }
+    println!(
+        "{}: {} record(s), {} invalid",
+        path.display(),
+        report.records,
+        report.invalid_records
+    );
+    for (violation, occurrences) in &report.violations {
+        let mut lines: Vec<String> = occurrences.lines.iter().map(|l| l.to_string()).collect();
+        if occurrences.count > lines.len() as u64 {
+            lines.push("...".to_string());
+        }
+        println!(
+            "  {}: {} ({} {})",
+            violation,
+            occurrences.count,
+            if lines.len() == 1 { "line" } else { "lines" },
+            lines.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE_CONFIG: &str = "schema:\n  metadata:\n    - name: readId\n      type: string\n    - name: samplingDate\n      type: date\n  primaryKey: readId\n";
+
+    #[test]
+    fn test_validate_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config = dir.join("database_config.yaml");
+        std::fs::write(&config, DATABASE_CONFIG).unwrap();
+        let schema = Schema::load(&config, None).unwrap();
+
+        let input = dir.join("input.ndjson.zst");
+        let content = [
+            r#"{"readId":"r1","samplingDate":"2025-07-03"}"#,
+            r#"{"samplingDate":"2025-07-03"}"#,
+            r#"{"readId":"r3","samplingDate":"03.07.2025"}"#,
+            r#"{"samplingDate":null}"#,
+            r#"{"samplingDate":null}"#,
+        ]
+        .join("\n");
+        std::fs::write(&input, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+
+        let report = validate_file(&input, &schema, 2).unwrap();
+        assert_eq!(report.records, 5);
+        assert_eq!(report.invalid_records, 4);
+        assert_eq!(
+            report.violations["missing primary key readId"],
+            ViolationCount {
+                count: 3,
+                lines: vec![2, 4]
+            }
+        );
+        assert_eq!(
+            report.violations["samplingDate is not a date (YYYY-MM-DD)"].lines,
+            vec![3]
+        );
+
+        let error = validate_file(&dir.join("missing.ndjson.zst"), &schema, 2).unwrap_err();
+        assert!(error.to_string().contains("missing.ndjson.zst"));
+    }
+}
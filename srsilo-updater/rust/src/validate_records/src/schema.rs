@@ -0,0 +1,273 @@
+//! The record schema from SILO's `database_config.yaml`.
+//!
+//! Each metadata field must be present in a record, with a value of its type
+//! or `null`; the primary key must be a non-empty string. With the reference
+//! genomes, fields that are neither metadata nor a sequence are flagged as
+//! unexpected. Sequence fields themselves are left to SILO.
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct DatabaseConfig {
+    schema: SchemaConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaConfig {
+    metadata: Vec<MetadataField>,
+    primary_key: String,
+}
+
+#[derive(Deserialize)]
+struct MetadataField {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: FieldType,
+}
+
+/// SILO metadata types.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FieldType {
+    String,
+    PangoLineage,
+    Date,
+    Int,
+    Float,
+    Boolean,
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::String | FieldType::PangoLineage => value.is_string(),
+            // SILO only reads zero-padded dates
+            FieldType::Date => value
+                .as_str()
+                .is_some_and(|s| s.len() == 10 && NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()),
+            FieldType::Int => value.is_i64(),
+            FieldType::Float => value.is_number(),
+            FieldType::Boolean => value.is_boolean(),
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::String => "a string",
+            FieldType::PangoLineage => "a pango lineage string",
+            FieldType::Date => "a date (YYYY-MM-DD)",
+            FieldType::Int => "an int",
+            FieldType::Float => "a number",
+            FieldType::Boolean => "a boolean",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReferenceGenomes {
+    nucleotide_sequences: Vec<NamedSequence>,
+    genes: Vec<NamedSequence>,
+}
+
+#[derive(Deserialize)]
+struct NamedSequence {
+    name: String,
+}
+
+/// What records are checked against.
+pub struct Schema {
+    fields: Vec<MetadataField>,
+    primary_key: String,
+    /// Metadata and sequence names, if the reference genomes were given
+    known_fields: Option<HashSet<String>>,
+}
+
+impl Schema {
+    pub fn load(database_config: &Path, reference_genomes: Option<&Path>) -> Result<Self, String> {
+        let content = fs::read_to_string(database_config)
+            .map_err(|e| format!("Failed to read {}: {}", database_config.display(), e))?;
+        let config: DatabaseConfig = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", database_config.display(), e))?;
+        let mut schema = Schema::new(config.schema)?;
+
+        if let Some(path) = reference_genomes {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let genomes: ReferenceGenomes = serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+            let mut known: HashSet<String> = schema.fields.iter().map(|f| f.name.clone()).collect();
+            known.extend(
+                genomes
+                    .nucleotide_sequences
+                    .into_iter()
+                    .chain(genomes.genes)
+                    .map(|sequence| sequence.name),
+            );
+            schema.known_fields = Some(known);
+        }
+        Ok(schema)
+    }
+
+    fn new(config: SchemaConfig) -> Result<Self, String> {
+        if !config.metadata.iter().any(|f| f.name == config.primary_key) {
+            return Err(format!(
+                "primary key {} is not a metadata field",
+                config.primary_key
+            ));
+        }
+        Ok(Schema {
+            fields: config.metadata,
+            primary_key: config.primary_key,
+            known_fields: None,
+        })
+    }
+
+    /// Returns the schema violations of one NDJSON line, empty if it is valid.
+    pub fn check(&self, line: &str) -> Vec<String> {
+        // Only the metadata values are parsed; sequences stay raw
+        let record: BTreeMap<String, &RawValue> = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(_) => return vec!["not a JSON object".to_string()],
+        };
+
+        let mut violations = Vec::new();
+        for field in &self.fields {
+            let is_primary_key = field.name == self.primary_key;
+            let Some(raw) = record.get(&field.name) else {
+                violations.push(if is_primary_key {
+                    format!("missing primary key {}", field.name)
+                } else {
+                    format!("missing field {}", field.name)
+                });
+                continue;
+            };
+            let value: Value = serde_json::from_str(raw.get()).expect("a RawValue is valid JSON");
+            if value.is_null() || value.as_str() == Some("") {
+                if is_primary_key {
+                    violations.push(format!("primary key {} is null or empty", field.name));
+                }
+                if value.is_null() {
+                    continue;
+                }
+            }
+            if !field.field_type.matches(&value) {
+                violations.push(format!("{} is not {}", field.name, field.field_type));
+            }
+        }
+
+        if let Some(known) = &self.known_fields {
+            for name in record.keys().filter(|name| !known.contains(*name)) {
+                violations.push(format!("unexpected field {}", name));
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE_CONFIG: &str = r#"
+schema:
+  metadata:
+    - name: sampleId
+      type: string
+      generateIndex: true
+    - name: samplingDate
+      type: date
+    - name: count
+      type: int
+    - name: readId
+      type: string
+  instanceName: test
+  primaryKey: readId
+"#;
+
+    fn schema(known: Option<&[&str]>) -> Schema {
+        let config: DatabaseConfig = serde_yaml::from_str(DATABASE_CONFIG).unwrap();
+        let mut schema = Schema::new(config.schema).unwrap();
+        schema.known_fields = known.map(|names| {
+            schema
+                .fields
+                .iter()
+                .map(|f| f.name.clone())
+                .chain(names.iter().map(|n| n.to_string()))
+                .collect()
+        });
+        schema
+    }
+
+    #[test]
+    fn test_valid_record() {
+        let line = r#"{"sampleId":"A1","samplingDate":"2025-07-03","count":null,"readId":"r1","main":{"sequence":"ACGT"}}"#;
+        assert!(schema(Some(&["main"])).check(line).is_empty());
+    }
+
+    #[test]
+    fn test_types_and_missing_fields() {
+        let line = r#"{"sampleId":1,"samplingDate":"2025-7-3","readId":"r1"}"#;
+        assert_eq!(
+            schema(None).check(line),
+            vec![
+                "sampleId is not a string",
+                "samplingDate is not a date (YYYY-MM-DD)",
+                "missing field count",
+            ]
+        );
+        let line = r#"{"sampleId":"A1","samplingDate":"2025-02-30","count":1.5,"readId":"r1"}"#;
+        assert_eq!(
+            schema(None).check(line),
+            vec![
+                "samplingDate is not a date (YYYY-MM-DD)",
+                "count is not an int"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_primary_key() {
+        let valid = r#""sampleId":"A1","samplingDate":null,"count":2"#;
+        assert_eq!(
+            schema(None).check(&format!("{{{}}}", valid)),
+            vec!["missing primary key readId"]
+        );
+        assert_eq!(
+            schema(None).check(&format!("{{{},\"readId\":null}}", valid)),
+            vec!["primary key readId is null or empty"]
+        );
+        assert_eq!(
+            schema(None).check(&format!("{{{},\"readId\":\"\"}}", valid)),
+            vec!["primary key readId is null or empty"]
+        );
+
+        let config: DatabaseConfig =
+            serde_yaml::from_str(&DATABASE_CONFIG.replace("primaryKey: readId", "primaryKey: id"))
+                .unwrap();
+        assert!(Schema::new(config.schema).is_err());
+    }
+
+    #[test]
+    fn test_unexpected_fields_and_invalid_json() {
+        let line = r#"{"sampleId":"A1","samplingDate":null,"count":1,"readId":"r1","main":null,"extra":1}"#;
+        assert_eq!(
+            schema(Some(&["main"])).check(line),
+            vec!["unexpected field extra"]
+        );
+        // Without the reference genomes, other fields are not checked
+        assert!(schema(None).check(line).is_empty());
+        assert_eq!(schema(None).check("[1]"), vec!["not a JSON object"]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Verify Sorted - WisePulse Data Pipeline
+//!
+//! Streams zstd-compressed NDJSON files and checks that their records are in
+//! order by the given sort keys, e.g. `sorted.ndjson.zst` before it is handed
+//! to SILO preprocessing, or the chunks of `split_into_sorted_chunks`. Each
+//! file is checked on its own; the first violations are reported with their
+//! line numbers.
+//!
+//! Exit codes:
+//! - 0: All files are sorted
+//! - 1: At least one file is out of order
+//! - 2: Error occurred (unreadable file, invalid record or sort key)
+
+use clap::Parser;
+use srsilo_common::notify::{Notifier, WEBHOOK_URL_ENV};
+use srsilo_common::sort_key::{self, KeyExtractor, MissingKeyPolicy, SortKey, SortKeySpec};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use zstd::stream::Decoder;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// zstd-compressed NDJSON files to check
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Sort key as `<json pointer>[:int|float|string|date[:asc|desc]]`; repeat or
+    /// separate with commas, as passed to split_into_sorted_chunks and merge_sorted_chunks
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "sort_field_path",
+        conflicts_with = "sort_field_path"
+    )]
+    sort_key: Vec<SortKeySpec>,
+
+    /// Single ascending integer sort field (same as `--sort-key <path>:int`)
+    #[arg(long)]
+    sort_field_path: Option<String>,
+
+    /// What to do with records whose sort key is missing or null:
+    /// error, first, last, drop or default=<value> (use the policy the file was sorted with)
+    #[arg(long, default_value = "error")]
+    missing_key: MissingKeyPolicy,
+
+    /// Number of violations to report per file; all of them are counted
+    #[arg(long, default_value_t = 10)]
+    max_violations: usize,
+
+    /// URL to POST an error event to as JSON if the check fails to run
+    #[arg(long, env = WEBHOOK_URL_ENV)]
+    webhook_url: Option<String>,
+}
+
+/// A record whose key is smaller than that of the record before it.
+#[derive(Debug, PartialEq)]
+struct Violation {
+    line: u64,
+    key: SortKey,
+    previous_line: u64,
+    previous_key: SortKey,
+}
+
+/// Result of checking one file.
+#[derive(Debug, Default, PartialEq)]
+struct FileReport {
+    records: u64,
+    violations: u64,
+    /// The first `--max-violations` violations
+    first_violations: Vec<Violation>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let notifier = Notifier::new(args.webhook_url.as_deref(), "verify_sorted");
+    notifier.report_panics();
+
+    let exit_code = match run(&args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            notifier.error(&e.to_string());
+            2
+        }
+    };
+    std::process::exit(exit_code);
+}
+
+/// Checks all files; returns whether all of them are sorted.
+fn run(args: &Args) -> io::Result<bool> {
+    let sort_keys = sort_key::resolve_sort_keys(&args.sort_key, args.sort_field_path.as_deref());
+    let extractor = KeyExtractor::new(sort_keys, args.missing_key.clone())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut all_sorted = true;
+    for input in &args.inputs {
+        let report = verify_file(input, &extractor, args.max_violations)?;
+        print_report(input, &report);
+        all_sorted &= report.violations == 0;
+    }
+
+    if let Some(summary) = extractor.summary() {
+        eprintln!("{}", summary);
+    }
+    Ok(all_sorted)
+}
+
+fn verify_file(
+    path: &Path,
+    extractor: &KeyExtractor,
+    max_violations: usize,
+) -> io::Result<FileReport> {
+    let file = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    verify(
+        BufReader::new(Decoder::new(file)?),
+        extractor,
+        max_violations,
+    )
+    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// Checks that each record's key is not smaller than the previous one's.
+/// Line numbers count all lines, including records dropped by the policy.
+fn verify<R: BufRead>(
+    reader: R,
+    extractor: &KeyExtractor,
+    max_violations: usize,
+) -> io::Result<FileReport> {
+    let mut report = FileReport::default();
+    let mut previous: Option<(u64, SortKey)> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index as u64 + 1;
+        let line = line?;
+        let key = match extractor.extract(&line) {
+            Ok(Some(key)) => key,
+            Ok(None) => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", line_number, e),
+                ))
+            }
+        };
+        report.records += 1;
+
+        if let Some((previous_line, previous_key)) = &previous {
+            if key < *previous_key {
+                report.violations += 1;
+                if report.first_violations.len() < max_violations {
+                    report.first_violations.push(Violation {
+                        line: line_number,
+                        key: key.clone(),
+                        previous_line: *previous_line,
+                        previous_key: previous_key.clone(),
+                    });
+                }
+            }
+        }
+        previous = Some((line_number, key));
+    }
+    Ok(report)
+}
+
+fn print_report(path: &Path, report: &FileReport) {
+    if report.violations == 0 {
+        println!("{}: {} record(s), sorted", path.display(), report.records);
+        return;
+    }
+    println!(
+        "{}: {} record(s), {} out of order",
+        path.display(),
+        report.records,
+        report.violations
+    );
+    for violation in &report.first_violations {
+        println!(
+            "  line {}: key {} is smaller than {} on line {}",
+            violation.line, violation.key, violation.previous_key, violation.previous_line
+        );
+    }
+    let unlisted = report.violations - report.first_violations.len() as u64;
+    if unlisted > 0 {
+        println!("  ... and {} more", unlisted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extractor(sort_keys: &str, policy: &str) -> KeyExtractor {
+        let sort_keys = sort_keys.split(',').map(|k| k.parse().unwrap()).collect();
+        KeyExtractor::new(sort_keys, policy.parse().unwrap()).unwrap()
+    }
+
+    fn check(content: &str, sort_keys: &str, max_violations: usize) -> io::Result<FileReport> {
+        verify(
+            content.as_bytes(),
+            &extractor(sort_keys, "error"),
+            max_violations,
+        )
+    }
+
+    #[test]
+    fn test_sorted_file() {
+        let content = "{\"o\":1,\"id\":\"b\"}\n{\"o\":1,\"id\":\"b\"}\n{\"o\":2,\"id\":\"a\"}";
+        let report = check(content, "/o:int,/id:string", 10).unwrap();
+        assert_eq!(report.records, 3);
+        assert_eq!(report.violations, 0);
+    }
+
+    #[test]
+    fn test_reports_first_violations_with_line_numbers() {
+        let content = "{\"o\":3}\n{\"o\":1}\n{\"o\":2}\n{\"o\":0}\n{\"o\":-1}\n";
+        let report = check(content, "/o", 2).unwrap();
+        assert_eq!(report.records, 5);
+        assert_eq!(report.violations, 3);
+        assert_eq!(report.first_violations.len(), 2);
+        let first = &report.first_violations[0];
+        assert_eq!((first.previous_line, first.line), (1, 2));
+        assert_eq!(first.key.to_string(), "(1)");
+        assert_eq!(first.previous_key.to_string(), "(3)");
+        assert_eq!(report.first_violations[1].line, 4);
+    }
+
+    #[test]
+    fn test_descending_keys() {
+        let content = "{\"d\":\"2025-07-03\"}\n{\"d\":\"2025-07-01\"}\n";
+        assert_eq!(check(content, "/d:date:desc", 10).unwrap().violations, 0);
+        assert_eq!(check(content, "/d:date", 10).unwrap().violations, 1);
+    }
+
+    #[test]
+    fn test_invalid_records_fail_with_line_number() {
+        let error = check("{\"o\":1}\n{\"x\":2}\n", "/o", 10).unwrap_err();
+        assert!(error.to_string().starts_with("line 2: Did not find field"));
+        assert!(check("{\"o\":1}\nnot json\n", "/o", 10).is_err());
+    }
+
+    #[test]
+    fn test_missing_key_policy() {
+        let content = "{\"o\":1}\n{\"x\":0}\n{\"o\":2}\n";
+        let report = verify(content.as_bytes(), &extractor("/o", "drop"), 10).unwrap();
+        assert_eq!((report.records, report.violations), (2, 0));
+        let report = verify(content.as_bytes(), &extractor("/o", "last"), 10).unwrap();
+        assert_eq!(report.first_violations[0].line, 3);
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.ndjson.zst");
+        std::fs::write(
+            &path,
+            zstd::encode_all(&b"{\"o\":1}\n{\"o\":2}\n"[..], 3).unwrap(),
+        )
+        .unwrap();
+        let report = verify_file(&path, &extractor("/o", "error"), 10).unwrap();
+        assert_eq!(report.records, 2);
+        std::fs::remove_file(&path).unwrap();
+
+        let error = verify_file(&path, &extractor("/o", "error"), 10).unwrap_err();
+        assert!(error.to_string().contains("input.ndjson.zst"));
+    }
+}
@@ -6,7 +6,7 @@ RUST_BINS = Path(__file__).parent.parent / "rust" / "target" / "release"
 
 @pytest.fixture(scope="session")
 def rust_bins():
-    required = ["check_new_data", "fetch_silo_data", "split_into_sorted_chunks", "merge_sorted_chunks"]
+    required = ["check_new_data", "fetch_silo_data", "split_into_sorted_chunks", "merge_sorted_chunks", "verify_sorted", "validate_records"]
     missing = [b for b in required if not (RUST_BINS / b).exists()]
     if missing:
         pytest.skip(
@@ -8,6 +8,9 @@ Covers the full run_virus() path:
   → preprocessing (real SILO Docker container)
   → timestamp promotion (__main__.py logic)
 
+and the multi-organism check of __main__.main() (check_new_data.run_all with
+one check_new_data invocation for several viruses, no Docker needed).
+
 Requires:
   - Rust binaries built:  cd rust && cargo build --release
   - Docker + SILO image:  ghcr.io/genspectrum/lapis-silo (pulled by Ansible or manually)
@@ -16,6 +19,7 @@ Requires:
 
 import json
 import shutil
+import time
 from datetime import date
 from pathlib import Path
 
@@ -24,10 +28,12 @@ from werkzeug import Request, Response
 
 from pipeline.__main__ import run_virus
 from pipeline.config import PipelineConfig, VirusConfig
+from pipeline.phases import check_new_data
 
 TEST_DATA = Path(__file__).parent / "data"
 _TEST_FILE = "sampleId-D1_10_2025_07_06.ndjson.zst"
-_FAKE_TIMESTAMP = 1751500000
+# Within the fetch window, so check_new_data's clock-skew checks accept it
+_FAKE_TIMESTAMP = int(time.time()) - 3600
 
 _SILO_CONFIG_SRC = Path("/opt/srsilo/covid/config")
 
@@ -104,15 +110,13 @@ def test_pipeline_e2e(httpserver, rust_bins, virus_dir):
     server_url = httpserver.url_for("").rstrip("/")
 
     def api_handler(request: Request) -> Response:
-        """Dispatch all /covid/sample/details calls based on query params."""
+        """Dispatch all /covid/sample/details calls based on method and query params."""
         args = request.args
 
-        # check_new_data: revocation check
-        if "isRevocation" in args:
-            return Response('{"data":[]}', content_type="application/json")
-
-        # check_new_data: new submissions check (first run, no .last_update)
-        if "submittedAtTimestampFrom" in args:
+        # check_new_data: latest submissions (POST, ordered by submittedAtTimestamp)
+        if request.method == "POST":
+            if request.get_json().get("isRevocation"):
+                return Response('{"data":[]}', content_type="application/json")
             body = json.dumps({
                 "data": [{"sampleId": "s1", "submittedAtTimestamp": _FAKE_TIMESTAMP}]
             })
@@ -134,6 +138,12 @@ def test_pipeline_e2e(httpserver, rust_bins, virus_dir):
         # All other dates → no data
         return Response('{"data":[]}', content_type="application/json")
 
+    def aggregated_handler(request: Request) -> Response:
+        """check_new_data: count of submissions or revocations since last update."""
+        count = 0 if "isRevocation" in request.args else 1
+        return Response(json.dumps({"data": [{"count": count}]}), content_type="application/json")
+
+    httpserver.expect_request("/covid/sample/aggregated").respond_with_handler(aggregated_handler)
     httpserver.expect_request("/covid/sample/details").respond_with_handler(api_handler)
 
     config = PipelineConfig(
@@ -164,9 +174,57 @@ def test_pipeline_e2e(httpserver, rust_bins, virus_dir):
     assert not paths.next_timestamp.exists(), ".next_timestamp was not cleaned up"
     assert paths.sorted_file.exists(), "sorted.ndjson.zst was not produced"
     assert paths.sorted_file.stat().st_size > 0, "sorted.ndjson.zst is empty"
-    assert paths.last_update.read_text().strip() == str(_FAKE_TIMESTAMP)
+    state = json.loads(paths.last_update.read_text())
+    assert state["last_processed_timestamp"] == _FAKE_TIMESTAMP
+    assert list(state["samples"]) == ["D1_10"]
 
     # SILO should have written an index directory under output/
     index_dirs = [d for d in paths.output.iterdir() if d.is_dir()]
     assert index_dirs, "SILO did not write any index directory to output/"
     assert (index_dirs[0] / "data_version.silo").exists(), "SILO index is incomplete"
+
+
+def test_check_all_viruses(httpserver, rust_bins, tmp_path):
+    """run_all checks several viruses at once and passes --force through."""
+
+    def aggregated_handler(request: Request) -> Response:
+        """covid has one new submission, rsva has nothing."""
+        new = request.path.startswith("/covid/") and "isRevocation" not in request.args
+        return Response(json.dumps({"data": [{"count": int(new)}]}), content_type="application/json")
+
+    def details_handler(request: Request) -> Response:
+        body = json.dumps({"data": [{"sampleId": "s1", "submittedAtTimestamp": _FAKE_TIMESTAMP}]})
+        return Response(body, content_type="application/json")
+
+    for organism in ("covid", "rsva"):
+        httpserver.expect_request(f"/{organism}/sample/aggregated").respond_with_handler(aggregated_handler)
+        httpserver.expect_request(f"/{organism}/sample/details").respond_with_handler(details_handler)
+
+    virus = {
+        "instance_name": "test",
+        "lapis_port": 8080,
+        "silo_port": 8081,
+        "fetch_days": 2,
+        "fetch_max_reads": 10_000_000,
+        "chunk_size": 30_000,
+        "docker_memory_limit": "4g",
+    }
+    pipeline_yml = tmp_path / "pipeline.yml"
+    pipeline_yml.write_text(json.dumps({
+        "base_path": str(tmp_path),
+        "tools_path": str(rust_bins.parent.parent),
+        "api_base_url": httpserver.url_for("").rstrip("/"),
+        "retention_days": 7,
+        "retention_min_keep": 1,
+        "enabled_viruses": ["covid", "rsva"],
+        "viruses": {name: {"organism": name, **virus} for name in ("covid", "rsva")},
+    }))
+    config = PipelineConfig.load(pipeline_yml)
+
+    assert check_new_data.run_all(config, ["covid", "rsva"]) == {"covid": True, "rsva": False}
+    assert config.virus_paths("covid").next_timestamp.exists(), ".next_timestamp was not written"
+    state = json.loads(config.virus_paths("covid").next_timestamp.read_text())
+    assert state["last_processed_timestamp"] == _FAKE_TIMESTAMP
+
+    # --force makes every virus run, as on a single-virus check
+    assert check_new_data.run_all(config, ["covid", "rsva"], ["--force"]) == {"covid": True, "rsva": True}